@@ -0,0 +1,102 @@
+//! Throughput of `HashMapComparer` with N reader threads and one writer thread.
+//!
+//! Readers call `is_same()`, `compare()` and `diff()` in a loop while the writer keeps updating,
+//! the number of finished calls per second is printed for every reader count.
+//!
+//! Run with `cargo bench --bench throughput`. Set `COMPARER_BENCH_MILLIS` to change how long
+//! every round runs and `COMPARER_BENCH_KEYS` to change the size of the hashmap.
+
+use std::collections::HashMap;
+use std::env;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use comparer::HashMapComparer;
+
+fn env_or(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn main() {
+    let duration = Duration::from_millis(env_or("COMPARER_BENCH_MILLIS", 1000));
+    let keys = env_or("COMPARER_BENCH_KEYS", 1000);
+
+    println!("{keys} keys, {duration:?} per round");
+    println!("{:>8} {:>16} {:>16}", "readers", "reads/sec", "updates/sec");
+    for readers in [1, 2, 4, 8] {
+        let (reads, updates) = round(readers, keys, duration);
+        let seconds = duration.as_secs_f64();
+        println!(
+            "{readers:>8} {:>16.0} {:>16.0}",
+            reads as f64 / seconds,
+            updates as f64 / seconds
+        );
+    }
+}
+
+/// Runs one round and returns how many reads and updates finished
+fn round(readers: usize, keys: u64, duration: Duration) -> (u64, u64) {
+    let comparer = HashMapComparer::<u64, u64>::new();
+    let base: HashMap<u64, u64> = (0..keys).map(|key| (key, key)).collect();
+    comparer.update(&base);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let reads = Arc::new(AtomicU64::new(0));
+
+    let reader_handles: Vec<_> = (0..readers)
+        .map(|reader| {
+            let comparer = comparer.clone();
+            let stop = stop.clone();
+            let reads = reads.clone();
+            let mut map = base.clone();
+            map.insert(reader as u64, u64::MAX);
+            thread::spawn(move || {
+                let mut done = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    match done % 3 {
+                        0 => {
+                            black_box(comparer.is_same(&map));
+                        }
+                        1 => {
+                            black_box(comparer.compare(&map).unwrap());
+                        }
+                        _ => {
+                            black_box(comparer.diff(&map));
+                        }
+                    }
+                    done += 1;
+                }
+                reads.fetch_add(done, Ordering::Relaxed);
+            })
+        })
+        .collect();
+
+    let writer = {
+        let comparer = comparer.clone();
+        let stop = stop.clone();
+        let mut map = base.clone();
+        thread::spawn(move || {
+            let mut done = 0;
+            while !stop.load(Ordering::Relaxed) {
+                map.insert(done % keys, done);
+                comparer.update(&map);
+                done += 1;
+            }
+            done
+        })
+    };
+
+    thread::sleep(duration);
+    stop.store(true, Ordering::Relaxed);
+    for handle in reader_handles {
+        handle.join().unwrap();
+    }
+    let updates = writer.join().unwrap();
+    (reads.load(Ordering::Relaxed), updates)
+}
@@ -0,0 +1,207 @@
+//! `#[derive(Diff)]` for the `Diff` trait of the `comparer` crate, enabled by its `derive` feature
+//!
+//! Structs report changes field by field, with the field name (or index for tuple structs) as path segment.
+//! Enums report changes of fields if both values are the same variant, otherwise the whole value is modified.
+//! Every field has to implement `Diff` unless it has one of these attributes:
+//!
+//! - `#[diff(skip)]` never reports the field
+//! - `#[diff(with = path)]` compares the field with a `fn(&T, &T) -> bool` and reports the whole field
+//!   as modified if it returns false. The field only has to implement `Debug`
+//!
+//! # Examples
+//! ```
+//!   use std::collections::HashMap;
+//!   use comparer::{Change, Diff, HashMapComparer};
+//!
+//!   fn same_host(a: &String, b: &String) -> bool {
+//!       a.eq_ignore_ascii_case(b)
+//!   }
+//!
+//!   #[derive(Debug, Clone, PartialEq, Diff)]
+//!   struct Server {
+//!       #[diff(with = same_host)]
+//!       host: String,
+//!       port: u16,
+//!       #[diff(skip)]
+//!       last_seen: u64,
+//!   }
+//!
+//!   let server = |host: &str, port, last_seen| Server { host: host.to_string(), port, last_seen };
+//!   let comparer = HashMapComparer::<&str, Server>::new();
+//!   comparer.update(&HashMap::from([("eu-1", server("a.example", 80, 1))]));
+//!
+//!   let changes = comparer.diff_nested(&HashMap::from([("eu-1", server("A.EXAMPLE", 8080, 2))]));
+//!   assert_eq!(1, changes.len());
+//!   assert_eq!(
+//!       Some(&Change::Modified { old: "80".to_string(), new: "8080".to_string() }),
+//!       changes.get(&"eu-1.port".to_string())
+//!   );
+//! ```
+//! Enums:
+//! ```
+//!   use comparer::{ChangeSet, Diff};
+//!
+//!   #[derive(Debug, Diff)]
+//!   enum Health {
+//!       Up { since: u64 },
+//!       Down(String),
+//!   }
+//!
+//!   let mut changes = ChangeSet::new();
+//!   Health::Up { since: 1 }.diff(&Health::Up { since: 2 }, "health", &mut changes);
+//!   Health::Down("disk".to_string()).diff(&Health::Up { since: 2 }, "other", &mut changes);
+//!   assert_eq!(r#"~ health.since: 1 -> 2
+//! ~ other: Down("disk") -> Up { since: 2 }
+//! 0 added, 0 removed, 2 modified"#, changes.to_string());
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Member, Path};
+
+#[proc_macro_derive(Diff, attributes(diff))]
+pub fn derive_diff(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// How a field is compared
+enum Strategy {
+    Diff,
+    Skip,
+    With(Path),
+}
+
+fn expand(mut input: DeriveInput) -> Result<TokenStream2, Error> {
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::comparer::Diff));
+    }
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let old = bindings(&data.fields, "old");
+            let new = bindings(&data.fields, "new");
+            let pattern_old = pattern(quote!(#name), &data.fields, &old);
+            let pattern_new = pattern(quote!(#name), &data.fields, &new);
+            let fields = diff_fields(&data.fields, &old, &new)?;
+            quote! {
+                let #pattern_old = self;
+                let #pattern_new = new;
+                #fields
+            }
+        }
+        Data::Enum(data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                let variant_name = &variant.ident;
+                let old = bindings(&variant.fields, "old");
+                let new = bindings(&variant.fields, "new");
+                let pattern_old = pattern(quote!(#name::#variant_name), &variant.fields, &old);
+                let pattern_new = pattern(quote!(#name::#variant_name), &variant.fields, &new);
+                let fields = diff_fields(&variant.fields, &old, &new)?;
+                arms.push(quote! {
+                    (#pattern_old, #pattern_new) => { #fields }
+                });
+            }
+            quote! {
+                #[allow(unreachable_patterns)]
+                match (self, new) {
+                    #(#arms)*
+                    _ => ::comparer::diff::modified(self, new, path, changes),
+                }
+            }
+        }
+        Data::Union(_) => return Err(Error::new(input.span(), "Diff can't be derived for unions")),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::comparer::Diff for #name #type_generics #where_clause {
+            #[allow(unused_variables)]
+            fn diff(
+                &self,
+                new: &Self,
+                path: &str,
+                changes: &mut ::comparer::ChangeSet<::std::string::String, ::std::string::String>,
+            ) {
+                #body
+            }
+        }
+    })
+}
+
+/// Names the fields are bound to when destructuring
+fn bindings(fields: &Fields, prefix: &str) -> Vec<Ident> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(index, _)| format_ident!("{}_{}", prefix, index))
+        .collect()
+}
+
+/// Pattern that destructures every field into its binding
+fn pattern(path: TokenStream2, fields: &Fields, bindings: &[Ident]) -> TokenStream2 {
+    let members = fields.members();
+    match fields {
+        Fields::Unit => path,
+        Fields::Named(_) | Fields::Unnamed(_) => {
+            quote!(#path { #(#members: #bindings),* })
+        }
+    }
+}
+
+fn diff_fields(fields: &Fields, old: &[Ident], new: &[Ident]) -> Result<TokenStream2, Error> {
+    let mut diffs = Vec::new();
+    for ((field, member), (old, new)) in
+        fields.iter().zip(fields.members()).zip(old.iter().zip(new))
+    {
+        let segment = match &member {
+            Member::Named(name) => name.to_string(),
+            Member::Unnamed(index) => index.index.to_string(),
+        };
+        let segment = segment.trim_start_matches("r#");
+        let field_path = quote!(&::comparer::diff::join(path, #segment));
+        diffs.push(match strategy(field)? {
+            Strategy::Skip => quote!(),
+            Strategy::Diff => quote! {
+                ::comparer::Diff::diff(#old, #new, #field_path, changes);
+            },
+            Strategy::With(eq) => quote! {
+                if !#eq(#old, #new) {
+                    ::comparer::diff::modified(#old, #new, #field_path, changes);
+                }
+            },
+        });
+    }
+    Ok(quote!(#(#diffs)*))
+}
+
+fn strategy(field: &syn::Field) -> Result<Strategy, Error> {
+    let mut strategy = Strategy::Diff;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("diff"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if !matches!(strategy, Strategy::Diff) {
+                return Err(meta.error("only one of `skip` and `with` can be used"));
+            }
+            if meta.path.is_ident("skip") {
+                strategy = Strategy::Skip;
+                Ok(())
+            } else if meta.path.is_ident("with") {
+                strategy = Strategy::With(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip` or `with = path`"))
+            }
+        })?;
+    }
+    Ok(strategy)
+}
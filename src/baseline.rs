@@ -0,0 +1,162 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::Arc;
+
+use crate::{ChangeSet, ComparerError, FirstRun, KeyFilter};
+
+/// Last hashmap of a comparer together with its generation and retained older generations
+#[derive(Debug)]
+pub(crate) struct Baseline<K, V> {
+    pub(crate) map: Arc<HashMap<K, V>>,
+    pub(crate) seeded: bool,
+    pub(crate) generation: u64,
+    /// Older generations, oldest first
+    history: VecDeque<(u64, Arc<HashMap<K, V>>)>,
+    history_depth: usize,
+    /// Hashmap each named consumer has seen when it last read changes
+    pub(crate) consumers: HashMap<String, Arc<HashMap<K, V>>>,
+    pub(crate) settings: Settings<K>,
+}
+
+/// Settings of a comparer. They are kept with last hashmap, so every handle of the comparer uses the same ones
+#[derive(Debug, Clone)]
+pub(crate) struct Settings<K> {
+    pub(crate) first_run: FirstRun,
+    pub(crate) key_filter: KeyFilter<K>,
+    pub(crate) max_keys: Option<usize>,
+}
+
+impl<K> Settings<K> {
+    pub(crate) fn check_capacity(&self, len: usize) -> Result<(), ComparerError> {
+        match self.max_keys {
+            Some(limit) if len > limit => Err(ComparerError::CapacityExceeded { limit, len }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<K, V> Baseline<K, V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            map: Arc::new(HashMap::new()),
+            seeded: false,
+            generation: 0,
+            history: VecDeque::new(),
+            history_depth: 0,
+            consumers: HashMap::new(),
+            settings: Settings {
+                first_run: FirstRun::default(),
+                key_filter: KeyFilter::all(),
+                max_keys: None,
+            },
+        }
+    }
+
+    /// Returns the hashmaps that no longer fit, so they can be dropped after the lock is released
+    pub(crate) fn set_history_depth(&mut self, depth: usize) -> Vec<Arc<HashMap<K, V>>> {
+        self.history_depth = depth;
+        let excess = self.history.len().saturating_sub(depth);
+        self.history.drain(..excess).map(|(_, map)| map).collect()
+    }
+
+    /// Replaces last hashmap, keeping the previous one in history.
+    /// Returns the hashmap that is no longer kept, so it can be dropped after the lock is released.
+    pub(crate) fn replace(
+        &mut self,
+        map: HashMap<K, V>,
+        seeded: bool,
+    ) -> Option<Arc<HashMap<K, V>>> {
+        let previous = std::mem::replace(&mut self.map, Arc::new(map));
+        self.generation += 1;
+        self.seeded = seeded;
+        if self.history_depth == 0 {
+            return Some(previous);
+        }
+        self.history.push_back((self.generation - 1, previous));
+        if self.history.len() > self.history_depth {
+            return self.history.pop_front().map(|(_, map)| map);
+        }
+        None
+    }
+
+    /// Replaces last hashmap with a saved one, continuing from its generation.
+    /// History belongs to the replaced generations, so it is dropped.
+    pub(crate) fn restore(&mut self, map: HashMap<K, V>, seeded: bool, generation: u64) {
+        self.map = Arc::new(map);
+        self.seeded = seeded;
+        self.generation = generation;
+        self.history.clear();
+    }
+
+    /// Returns hashmap of a generation if it is the current one or still kept in history
+    pub(crate) fn snapshot(&self, generation: u64) -> Option<&Arc<HashMap<K, V>>> {
+        if generation == self.generation {
+            return Some(&self.map);
+        }
+        self.history
+            .iter()
+            .find(|(kept, _)| *kept == generation)
+            .map(|(_, map)| map)
+    }
+
+    /// Oldest generation that can still be returned by `snapshot()`
+    pub(crate) fn oldest_generation(&self) -> u64 {
+        self.history
+            .front()
+            .map_or(self.generation, |(generation, _)| *generation)
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Baseline<K, V> {
+    /// Copies last hashmap, generation, history and settings without the consumers.
+    /// Hashmaps are immutable once stored, so they are shared instead of cloned.
+    pub(crate) fn fork(&self) -> Self {
+        Self {
+            map: self.map.clone(),
+            seeded: self.seeded,
+            generation: self.generation,
+            history: self.history.clone(),
+            history_depth: self.history_depth,
+            consumers: HashMap::new(),
+            settings: self.settings.clone(),
+        }
+    }
+
+    /// Applies changes to last hashmap as a new generation. Done in place unless the current hashmap
+    /// has to be kept for history or anything else still holds it: a snapshot, a fork, a consumer cursor,
+    /// a waiting `wait_for_change()` or a running comparison. Then it is copied first.
+    pub(crate) fn apply(&mut self, changes: &ChangeSet<K, V>) {
+        if self.history_depth > 0 {
+            let mut map = HashMap::clone(&self.map);
+            changes.apply_to(&mut map);
+            self.replace(map, true);
+        } else {
+            changes.apply_to(Arc::make_mut(&mut self.map));
+            self.generation += 1;
+            self.seeded = true;
+        }
+    }
+
+    /// Sets the key filter and drops the keys it ignores from last hashmap, history and consumer cursors.
+    /// Doesn't count as an update. Returns the hashmaps that were replaced, so they can be dropped
+    /// after the lock is released.
+    pub(crate) fn set_key_filter(&mut self, key_filter: KeyFilter<K>) -> Vec<Arc<HashMap<K, V>>> {
+        let mut replaced = Vec::new();
+        let mut filter = |map: &mut Arc<HashMap<K, V>>| {
+            if map.keys().all(|key| key_filter.allows(key)) {
+                return;
+            }
+            let filtered = map
+                .iter()
+                .filter(|(key, _)| key_filter.allows(key))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+            replaced.push(std::mem::replace(map, Arc::new(filtered)));
+        };
+        filter(&mut self.map);
+        self.history.iter_mut().for_each(|(_, map)| filter(map));
+        self.consumers.values_mut().for_each(filter);
+        self.settings.key_filter = key_filter;
+        replaced
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasher, Hash};
+
+use crate::{FirstRun, MapLike};
+
+/// Single difference between the last hashmap and a new one
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Change<V> {
+    /// Key exists only in the new hashmap
+    Added(V),
+    /// Key exists only in the last hashmap
+    Removed(V),
+    /// Key exists in both hashmaps but its value changed
+    Modified { old: V, new: V },
+    /// Key is part of the first hashmap a comparer has seen, see `FirstRun::Snapshot`
+    Initial(V),
+}
+
+impl<V> Change<V> {
+    /// Returns value before the change, `None` for added keys
+    pub fn old_value(&self) -> Option<&V> {
+        match self {
+            Change::Added(_) | Change::Initial(_) => None,
+            Change::Removed(old) | Change::Modified { old, .. } => Some(old),
+        }
+    }
+
+    /// Returns value after the change, `None` for removed keys
+    pub fn new_value(&self) -> Option<&V> {
+        match self {
+            Change::Removed(_) => None,
+            Change::Added(new) | Change::Modified { new, .. } | Change::Initial(new) => Some(new),
+        }
+    }
+}
+
+/// Every difference between two hashmaps, keyed by the key that changed
+///
+/// Returned by `HashMapComparer::diff()` and `HashMapComparer::update_and_diff()`.
+/// Unlike `compare()` it also reports keys that were removed and keeps old values of modified keys.
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::{Change, HashMapComparer};
+///
+///   let comparer = HashMapComparer::<u8, &str>::new();
+///   comparer.update(&HashMap::from([(1, "foo"), (2, "bar")]));
+///
+///   let changes = comparer.diff(&HashMap::from([(2, "baz"), (3, "foo")]));
+///   assert_eq!(3, changes.len());
+///   assert_eq!(Some(&Change::Removed("foo")), changes.get(&1));
+///   assert_eq!(Some(&Change::Modified { old: "bar", new: "baz" }), changes.get(&2));
+///   assert_eq!(Some(&Change::Added("foo")), changes.get(&3));
+/// ```
+/// With the `serde` feature it serializes as a list of `[key, change]` pairs:
+/// ```
+///   # #[cfg(feature = "serde")]
+///   # {
+///   use std::collections::HashMap;
+///   use comparer::{ChangeSet, HashMapComparer};
+///
+///   let comparer = HashMapComparer::<u8, &str>::new();
+///   comparer.update(&HashMap::from([(1, "foo")]));
+///   let changes = comparer.diff(&HashMap::from([(1, "bar")]));
+///
+///   let json = serde_json::to_string(&changes).unwrap();
+///   assert_eq!(r#"[[1,{"modified":{"old":"foo","new":"bar"}}]]"#, json);
+///   assert_eq!(changes, serde_json::from_str::<ChangeSet<u8, &str>>(&json).unwrap());
+///   # }
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct ChangeSet<K, V> {
+    changes: Vec<(K, Change<V>)>,
+}
+
+impl<K, V> ChangeSet<K, V> {
+    /// Creates an empty change set
+    pub fn new() -> Self {
+        Self {
+            changes: Vec::new(),
+        }
+    }
+
+    /// Number of changed keys
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    /// Returns true if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Iterates over every changed key and its change
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &Change<V>)> {
+        self.changes.iter().map(|(key, change)| (key, change))
+    }
+
+    /// Iterates over keys that were added, with their new values
+    pub fn added(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.changes
+            .iter()
+            .filter_map(|(key, change)| match change {
+                Change::Added(value) => Some((key, value)),
+                _ => None,
+            })
+    }
+
+    /// Iterates over keys that were removed, with their last values
+    pub fn removed(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.changes
+            .iter()
+            .filter_map(|(key, change)| match change {
+                Change::Removed(value) => Some((key, value)),
+                _ => None,
+            })
+    }
+
+    /// Iterates over keys that were modified, with their old and new values
+    pub fn modified(&self) -> impl Iterator<Item = (&K, &V, &V)> {
+        self.changes
+            .iter()
+            .filter_map(|(key, change)| match change {
+                Change::Modified { old, new } => Some((key, old, new)),
+                _ => None,
+            })
+    }
+
+    /// Returns true if this is the first snapshot taken with `FirstRun::Snapshot` rather than a list of changes
+    pub fn is_initial(&self) -> bool {
+        self.changes
+            .iter()
+            .any(|(_, change)| matches!(change, Change::Initial(_)))
+    }
+
+    /// Sorts changes by key
+    pub fn sort(&mut self)
+    where
+        K: Ord,
+    {
+        self.changes.sort_by(|(a, _), (b, _)| a.cmp(b));
+    }
+
+    pub(crate) fn push(&mut self, key: K, change: Change<V>) {
+        self.changes.push((key, change));
+    }
+}
+
+impl<K: PartialEq, V> ChangeSet<K, V> {
+    /// Returns change of a key, `None` if the key didn't change
+    pub fn get(&self, key: &K) -> Option<&Change<V>> {
+        self.changes
+            .iter()
+            .find(|(changed, _)| changed == key)
+            .map(|(_, change)| change)
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> ChangeSet<K, V> {
+    /// Applies changes to a hashmap: added, modified and initial keys are inserted with their new values
+    /// and removed keys are removed. Old values are not checked, so the hashmap doesn't have to match
+    /// the one the changes were detected against.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   let mut replica = HashMap::<u8, &str>::new();
+    ///
+    ///   let mut my_hashmap = HashMap::<u8, &str>::from_iter(vec![(1, "foo"), (2, "bar")]);
+    ///   comparer.update_and_diff(&my_hashmap).apply_to(&mut replica);
+    ///   my_hashmap.remove(&1);
+    ///   my_hashmap.insert(2, "baz");
+    ///   let changes = comparer.update_and_diff(&my_hashmap);
+    ///   changes.apply_to(&mut replica);
+    ///   assert_eq!(my_hashmap, replica);
+    ///
+    ///   // Roll the bad update back
+    ///   changes.invert().apply_to(&mut replica);
+    ///   assert_eq!(HashMap::from([(1, "foo"), (2, "bar")]), replica);
+    /// ```
+    pub fn apply_to<S: BuildHasher>(&self, map: &mut HashMap<K, V, S>) {
+        for (key, change) in self.iter() {
+            match change.new_value() {
+                Some(value) => {
+                    map.insert(key.clone(), value.clone());
+                }
+                None => {
+                    map.remove(key);
+                }
+            }
+        }
+    }
+
+    /// Returns changes that undo these changes: added keys become removed, removed keys become added
+    /// and old and new values of modified keys are swapped
+    pub fn invert(&self) -> ChangeSet<K, V> {
+        self.iter()
+            .map(|(key, change)| {
+                let inverted = match change {
+                    Change::Added(value) | Change::Initial(value) => Change::Removed(value.clone()),
+                    Change::Removed(value) => Change::Added(value.clone()),
+                    Change::Modified { old, new } => Change::Modified {
+                        old: new.clone(),
+                        new: old.clone(),
+                    },
+                };
+                (key.clone(), inverted)
+            })
+            .collect()
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + PartialEq> ChangeSet<K, V> {
+    /// Squashes these changes and the `next` ones, detected right after them, into their net effect.
+    /// A key added and then removed disappears from the result, as does a key changed back to its old value.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{Change, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   comparer.update(&HashMap::from([(1, "foo"), (2, "bar")]));
+    ///   let first = comparer.update_and_diff(&HashMap::from([(1, "baz"), (2, "bar"), (3, "foo")]));
+    ///   let second = comparer.update_and_diff(&HashMap::from([(1, "foo"), (3, "bar")]));
+    ///
+    ///   let net = first.compose(&second);
+    ///   assert_eq!(2, net.len());
+    ///   assert_eq!(Some(&Change::Removed("bar")), net.get(&2));
+    ///   assert_eq!(Some(&Change::Added("bar")), net.get(&3));
+    /// ```
+    pub fn compose(&self, next: &ChangeSet<K, V>) -> ChangeSet<K, V> {
+        let later: HashMap<&K, &Change<V>> = next.iter().collect();
+        let mut composed = ChangeSet::new();
+        for (key, first) in self.iter() {
+            match later.get(key) {
+                None => composed.push(key.clone(), first.clone()),
+                Some(second) => {
+                    if let Some(change) = net_change(first, second) {
+                        composed.push(key.clone(), change);
+                    }
+                }
+            }
+        }
+        let earlier: HashSet<&K> = self.iter().map(|(key, _)| key).collect();
+        for (key, second) in next.iter() {
+            if !earlier.contains(key) {
+                composed.push(key.clone(), second.clone());
+            }
+        }
+        composed
+    }
+}
+
+/// Net effect of two consecutive changes of the same key
+fn net_change<V: Clone + PartialEq>(first: &Change<V>, second: &Change<V>) -> Option<Change<V>> {
+    match (first.old_value(), second.new_value()) {
+        (None, None) => None,
+        (None, Some(new)) if matches!(first, Change::Initial(_)) => {
+            Some(Change::Initial(new.clone()))
+        }
+        (None, Some(new)) => Some(Change::Added(new.clone())),
+        (Some(old), None) => Some(Change::Removed(old.clone())),
+        (Some(old), Some(new)) if old == new => None,
+        (Some(old), Some(new)) => Some(Change::Modified {
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
+
+impl<K, V> Default for ChangeSet<K, V> {
+    fn default() -> Self {
+        ChangeSet::new()
+    }
+}
+
+/// Two change sets are equal if they contain the same changes, in any order
+impl<K: Eq + Hash, V: PartialEq> PartialEq for ChangeSet<K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        let other: HashMap<&K, &Change<V>> = other.iter().collect();
+        self.iter()
+            .all(|(key, change)| other.get(key).is_some_and(|other| *other == change))
+    }
+}
+
+impl<K: Eq + Hash, V: Eq> Eq for ChangeSet<K, V> {}
+
+impl<K, V> FromIterator<(K, Change<V>)> for ChangeSet<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, Change<V>)>>(iter: I) -> Self {
+        Self {
+            changes: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<K, V> IntoIterator for ChangeSet<K, V> {
+    type Item = (K, Change<V>);
+    type IntoIter = std::vec::IntoIter<(K, Change<V>)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.changes.into_iter()
+    }
+}
+
+/// Checks that both maps contain the same keys with equal values, ignoring iteration order
+pub(crate) fn same_maps<K, V>(
+    a: &impl MapLike<K, V>,
+    b: &impl MapLike<K, V>,
+    eq: impl Fn(&V, &V) -> bool,
+) -> bool {
+    a.len() == b.len()
+        && a.iter()
+            .all(|(key, value)| b.get(key).is_some_and(|other| eq(value, other)))
+}
+
+/// Compares `new` to `old` and collects added, removed and modified keys,
+/// in the key order of `new` if it has one
+pub(crate) fn diff_maps<K: Clone, V: Clone, M: MapLike<K, V>>(
+    old: &impl MapLike<K, V>,
+    new: &M,
+    eq: impl Fn(&V, &V) -> bool,
+) -> ChangeSet<K, V> {
+    let mut changes = ChangeSet::new();
+    for (key, value) in new.iter() {
+        match old.get(key) {
+            None => changes.push(key.clone(), Change::Added(value.clone())),
+            Some(old_value) if !eq(old_value, value) => changes.push(
+                key.clone(),
+                Change::Modified {
+                    old: old_value.clone(),
+                    new: value.clone(),
+                },
+            ),
+            Some(_) => {}
+        }
+    }
+    for (key, value) in old.iter() {
+        if !new.contains_key(key) {
+            changes.push(key.clone(), Change::Removed(value.clone()));
+        }
+    }
+    M::order_changes(&mut changes);
+    changes
+}
+
+/// Collects added and modified values of `new_map`, following the first run policy if `last` was never set
+pub(crate) fn changed_values<K: Clone + Eq + Hash, V: Clone>(
+    last: &HashMap<K, V>,
+    seeded: bool,
+    first_run: FirstRun,
+    new_map: &impl MapLike<K, V>,
+    eq: impl Fn(&V, &V) -> bool,
+) -> HashMap<K, V> {
+    let mut changed_values: HashMap<K, V> = HashMap::new();
+    if !seeded && first_run == FirstRun::ReportNothing {
+        return changed_values;
+    }
+    for (key, value) in new_map.iter() {
+        if !seeded || !last.get(key).is_some_and(|old_value| eq(old_value, value)) {
+            changed_values.insert(key.clone(), value.clone());
+        }
+    }
+    changed_values
+}
+
+/// Diffs `new_map` against `last`, following the first run policy if `last` was never set
+pub(crate) fn diff_last<K: Clone + Eq + Hash, V: Clone, M: MapLike<K, V>>(
+    last: &HashMap<K, V>,
+    seeded: bool,
+    first_run: FirstRun,
+    new_map: &M,
+    eq: impl Fn(&V, &V) -> bool,
+) -> ChangeSet<K, V> {
+    if !seeded {
+        return match first_run {
+            FirstRun::ReportNothing => ChangeSet::new(),
+            FirstRun::ReportAll => diff_maps(&HashMap::new(), new_map, eq),
+            FirstRun::Snapshot => {
+                let mut changes: ChangeSet<K, V> = new_map
+                    .iter()
+                    .map(|(key, value)| (key.clone(), Change::Initial(value.clone())))
+                    .collect();
+                M::order_changes(&mut changes);
+                changes
+            }
+        };
+    }
+    diff_maps(last, new_map, eq)
+}
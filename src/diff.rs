@@ -0,0 +1,268 @@
+//! Diffing of nested values, e.g. hashmaps of hashmaps or structs, down to the fields that changed
+//!
+//! `Diff` reports changes as a `ChangeSet<String, String>` keyed by dotted paths like `servers.eu-1.port`.
+//! Values are shown with `Debug`, so changes of differently typed fields fit into one change set.
+//! Map keys become path segments with `Display`, vector elements and tuple fields by their index.
+//! Segments are escaped like JSON Pointer segments, `~` as `~0` and `.` as `~1`, so a key containing a dot
+//! can't be mistaken for a nested one.
+//! # Examples
+//! ```
+//!   use std::collections::HashMap;
+//!   use comparer::{Change, HashMapComparer};
+//!
+//!   let comparer = HashMapComparer::<&str, HashMap<&str, (String, u16)>>::new();
+//!   comparer.update(&HashMap::from([(
+//!       "servers",
+//!       HashMap::from([("eu-1", ("10.0.0.1".to_string(), 80)), ("us-1", ("10.0.1.1".to_string(), 80))]),
+//!   )]));
+//!
+//!   let changes = comparer.diff_nested(&HashMap::from([(
+//!       "servers",
+//!       HashMap::from([("eu-1", ("10.0.0.1".to_string(), 8080)), ("us-2", ("10.0.2.1".to_string(), 80))]),
+//!   )]));
+//!   assert_eq!(3, changes.len());
+//!   assert_eq!(Some(&Change::Modified { old: "80".to_string(), new: "8080".to_string() }), changes.get(&"servers.eu-1.1".to_string()));
+//!   assert_eq!(Some(&Change::Removed(r#"("10.0.1.1", 80)"#.to_string())), changes.get(&"servers.us-1".to_string()));
+//!   assert!(matches!(changes.get(&"servers.us-2".to_string()), Some(Change::Added(_))));
+//! ```
+//! Keys with dots:
+//! ```
+//!   use std::collections::HashMap;
+//!   use comparer::HashMapComparer;
+//!
+//!   let comparer = HashMapComparer::<&str, HashMap<&str, u8>>::new();
+//!   comparer.update(&HashMap::from([("a.b", HashMap::from([("c", 1)])), ("a", HashMap::from([("b.c", 1)]))]));
+//!
+//!   let changes = comparer.diff_nested(&HashMap::from([
+//!       ("a.b", HashMap::from([("c", 2)])),
+//!       ("a", HashMap::from([("b.c", 2)])),
+//!   ]));
+//!   let paths: Vec<_> = changes.iter().map(|(path, _)| path.as_str()).collect();
+//!   assert_eq!(vec!["a.b~1c", "a~1b.c"], paths);
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Debug, Display};
+use std::hash::{BuildHasher, Hash};
+
+use crate::{Change, ChangeSet, ComparerError, FirstRun, HashMapComparer, MapLike};
+
+/// Value that can report which of its parts changed, see `diff` module
+pub trait Diff: Debug {
+    /// Adds changes from `self` to `new` to `changes`, with paths starting at `path`
+    fn diff(&self, new: &Self, path: &str, changes: &mut ChangeSet<String, String>);
+}
+
+/// Appends a segment to a dotted path, escaping `~` as `~0` and `.` as `~1` in the segment
+pub fn join(path: &str, segment: impl Display) -> String {
+    let segment = segment.to_string().replace('~', "~0").replace('.', "~1");
+    if path.is_empty() {
+        segment
+    } else {
+        format!("{path}.{segment}")
+    }
+}
+
+/// Reports the whole value at `path` as modified
+pub fn modified<T: Debug + ?Sized>(
+    old: &T,
+    new: &T,
+    path: &str,
+    changes: &mut ChangeSet<String, String>,
+) {
+    changes.push(
+        path.to_string(),
+        Change::Modified {
+            old: format!("{old:?}"),
+            new: format!("{new:?}"),
+        },
+    );
+}
+
+/// Reports `old` as modified to `new` if they aren't equal, for values without parts
+pub fn leaf<T: Debug + PartialEq + ?Sized>(
+    old: &T,
+    new: &T,
+    path: &str,
+    changes: &mut ChangeSet<String, String>,
+) {
+    if old != new {
+        modified(old, new, path, changes);
+    }
+}
+
+macro_rules! diff_leaf {
+    ($($leaf:ty),*) => {
+        $(
+            impl Diff for $leaf {
+                fn diff(&self, new: &Self, path: &str, changes: &mut ChangeSet<String, String>) {
+                    leaf(self, new, path, changes);
+                }
+            }
+        )*
+    };
+}
+
+diff_leaf!(
+    bool, char, u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, str,
+    String
+);
+
+impl<T: Diff + ?Sized> Diff for &T {
+    fn diff(&self, new: &Self, path: &str, changes: &mut ChangeSet<String, String>) {
+        (**self).diff(new, path, changes);
+    }
+}
+
+impl<T: Diff> Diff for Option<T> {
+    fn diff(&self, new: &Self, path: &str, changes: &mut ChangeSet<String, String>) {
+        match (self, new) {
+            (Some(old), Some(new)) => old.diff(new, path, changes),
+            (None, Some(new)) => changes.push(path.to_string(), Change::Added(format!("{new:?}"))),
+            (Some(old), None) => {
+                changes.push(path.to_string(), Change::Removed(format!("{old:?}")))
+            }
+            (None, None) => {}
+        }
+    }
+}
+
+/// Elements are matched by index, elements past the end of the shorter vector are added or removed
+impl<T: Diff> Diff for Vec<T> {
+    fn diff(&self, new: &Self, path: &str, changes: &mut ChangeSet<String, String>) {
+        for (index, (old, new)) in self.iter().zip(new).enumerate() {
+            old.diff(new, &join(path, index), changes);
+        }
+        for (index, new) in new.iter().enumerate().skip(self.len()) {
+            changes.push(join(path, index), Change::Added(format!("{new:?}")));
+        }
+        for (index, old) in self.iter().enumerate().skip(new.len()) {
+            changes.push(join(path, index), Change::Removed(format!("{old:?}")));
+        }
+    }
+}
+
+macro_rules! diff_tuple {
+    ($($name:ident $index:tt),*) => {
+        impl<$($name: Diff),*> Diff for ($($name,)*) {
+            fn diff(&self, new: &Self, path: &str, changes: &mut ChangeSet<String, String>) {
+                $(self.$index.diff(&new.$index, &join(path, $index), changes);)*
+            }
+        }
+    };
+}
+
+diff_tuple!(A 0);
+diff_tuple!(A 0, B 1);
+diff_tuple!(A 0, B 1, C 2);
+diff_tuple!(A 0, B 1, C 2, D 3);
+
+impl<K: Display + Eq + Hash + Debug, V: Diff, S: BuildHasher> Diff for HashMap<K, V, S> {
+    fn diff(&self, new: &Self, path: &str, changes: &mut ChangeSet<String, String>) {
+        diff_entries(self, new, path, changes);
+    }
+}
+
+impl<K: Display + Ord + Debug, V: Diff> Diff for BTreeMap<K, V> {
+    fn diff(&self, new: &Self, path: &str, changes: &mut ChangeSet<String, String>) {
+        diff_entries(self, new, path, changes);
+    }
+}
+
+fn diff_entries<K: Display, V: Diff>(
+    old: &impl MapLike<K, V>,
+    new: &impl MapLike<K, V>,
+    path: &str,
+    changes: &mut ChangeSet<String, String>,
+) {
+    for (key, value) in new.iter() {
+        let path = join(path, key);
+        match old.get(key) {
+            Some(old) => old.diff(value, &path, changes),
+            None => changes.push(path, Change::Added(format!("{value:?}"))),
+        }
+    }
+    for (key, value) in old.iter() {
+        if !new.contains_key(key) {
+            changes.push(join(path, key), Change::Removed(format!("{value:?}")));
+        }
+    }
+}
+
+impl<K: Clone + Eq + Hash + Display, V: Clone + Diff> HashMapComparer<K, V> {
+    /// Compares new hashmap to the last one like `diff()`, but reports changes inside modified values
+    /// by their path, see `diff` module. Changes are sorted by path.
+    /// Values the equality function of the comparer treats as the same are not looked into.
+    pub fn diff_nested<M: MapLike<K, V>>(&self, new_map: &M) -> ChangeSet<String, String> {
+        crate::or_panic(self.try_diff_nested(new_map))
+    }
+
+    /// Same as `diff_nested()`, but returns an error instead of panicking
+    pub fn try_diff_nested<M: MapLike<K, V>>(
+        &self,
+        new_map: &M,
+    ) -> Result<ChangeSet<String, String>, ComparerError> {
+        let (last, seeded, settings) = self.current()?;
+        let new_map = &settings.key_filter.view(new_map);
+        Ok(self.nested_changes(&last, seeded, settings.first_run, new_map))
+    }
+
+    /// Updates last hashmap and returns changes inside modified values, see `diff_nested()`
+    pub fn update_and_diff_nested<M: MapLike<K, V>>(
+        &self,
+        new_map: &M,
+    ) -> ChangeSet<String, String> {
+        crate::or_panic(self.try_update_and_diff_nested(new_map))
+    }
+
+    /// Same as `update_and_diff_nested()`, but returns an error instead of panicking
+    pub fn try_update_and_diff_nested<M: MapLike<K, V>>(
+        &self,
+        new_map: &M,
+    ) -> Result<ChangeSet<String, String>, ComparerError> {
+        let writer = self.lock_writer()?;
+        let (last, seeded, settings) = self.current()?;
+        let new_map = &settings.key_filter.view(new_map);
+        settings.check_capacity(new_map.len())?;
+        let nested = self.nested_changes(&last, seeded, settings.first_run, new_map);
+        let changes = self
+            .subscribers
+            .is_active()
+            .then(|| self.changes(&last, seeded, settings.first_run, new_map));
+        drop(last);
+        self.swap(new_map)?;
+        self.updated(writer, changes);
+        Ok(nested)
+    }
+
+    fn nested_changes(
+        &self,
+        last: &HashMap<K, V>,
+        seeded: bool,
+        first_run: FirstRun,
+        new_map: &impl MapLike<K, V>,
+    ) -> ChangeSet<String, String> {
+        let mut changes = ChangeSet::new();
+        if !seeded && first_run == FirstRun::ReportNothing {
+            return changes;
+        }
+        for (key, value) in new_map.iter() {
+            let path = join("", key);
+            match last.get(key) {
+                Some(old) if (self.eq)(old, value) => {}
+                Some(old) => old.diff(value, &path, &mut changes),
+                None if !seeded && first_run == FirstRun::Snapshot => {
+                    changes.push(path, Change::Initial(format!("{value:?}")))
+                }
+                None => changes.push(path, Change::Added(format!("{value:?}"))),
+            }
+        }
+        for (key, value) in last.iter() {
+            if !new_map.contains_key(key) {
+                changes.push(join("", key), Change::Removed(format!("{value:?}")));
+            }
+        }
+        changes.sort();
+        changes
+    }
+}
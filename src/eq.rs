@@ -0,0 +1,79 @@
+//! Ready made equality strategies for `HashMapComparer::with_eq()`
+//! # Examples
+//! ```
+//!   use std::collections::HashMap;
+//!   use comparer::{eq, HashMapComparer};
+//!
+//!   let comparer = HashMapComparer::<&str, f64>::with_eq(eq::absolute(0.01));
+//!   comparer.update(&HashMap::from([("temperature", 21.50), ("humidity", 40.0)]));
+//!
+//!   // Jitter within the tolerance isn't a change
+//!   let readings = HashMap::from([("temperature", 21.505), ("humidity", 41.0)]);
+//!   assert!(!comparer.is_same(&readings));
+//!   assert_eq!(HashMap::from([("humidity", 41.0)]), comparer.compare(&readings).unwrap());
+//! ```
+
+/// Floats are equal if they differ by at most `epsilon`. Two NaNs are equal.
+pub fn absolute(epsilon: f64) -> impl Fn(&f64, &f64) -> bool + Clone + Send + Sync + 'static {
+    move |a, b| a == b || (a - b).abs() <= epsilon || (a.is_nan() && b.is_nan())
+}
+
+/// Floats are equal if they differ by at most `tolerance` times the larger of their magnitudes,
+/// e.g. `relative(0.01)` treats values within 1% of each other as equal. Two NaNs are equal.
+/// # Examples
+/// ```
+///   use comparer::eq;
+///
+///   let within_percent = eq::relative(0.01);
+///   assert!(within_percent(&1000.0, &1009.0));
+///   assert!(!within_percent(&1.0, &1.02));
+///   assert!(within_percent(&f64::NAN, &f64::NAN));
+/// ```
+pub fn relative(tolerance: f64) -> impl Fn(&f64, &f64) -> bool + Clone + Send + Sync + 'static {
+    move |a, b| {
+        a == b || (a - b).abs() <= tolerance * a.abs().max(b.abs()) || (a.is_nan() && b.is_nan())
+    }
+}
+
+/// Strings are equal if they only differ in letter case
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::{eq, HashMapComparer};
+///
+///   let comparer = HashMapComparer::<u8, String>::with_eq(eq::case_insensitive);
+///   comparer.update(&HashMap::from([(1, "Größe".to_string())]));
+///   assert!(comparer.is_same(&HashMap::from([(1, "GRÖßE".to_string())])));
+///   assert!(!comparer.is_same(&HashMap::from([(1, "Grösse".to_string())])));
+/// ```
+pub fn case_insensitive<S: AsRef<str>>(a: &S, b: &S) -> bool {
+    let (a, b) = (a.as_ref(), b.as_ref());
+    a == b
+        || a.chars()
+            .flat_map(char::to_lowercase)
+            .eq(b.chars().flat_map(char::to_lowercase))
+}
+
+/// Values are equal if the projection returns equal results for them,
+/// e.g. to compare structs by one of their fields
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::{eq, HashMapComparer};
+///
+///   #[derive(Clone)]
+///   struct Session {
+///       user: String,
+///       last_seen: u64,
+///   }
+///
+///   let comparer = HashMapComparer::<u8, Session>::with_eq(eq::by(|session: &Session| session.user.clone()));
+///   comparer.update(&HashMap::from([(1, Session { user: "foo".to_string(), last_seen: 10 })]));
+///   assert!(comparer.is_same(&HashMap::from([(1, Session { user: "foo".to_string(), last_seen: 20 })])));
+///   assert!(!comparer.is_same(&HashMap::from([(1, Session { user: "bar".to_string(), last_seen: 20 })])));
+/// ```
+pub fn by<V, T: PartialEq>(
+    projection: impl Fn(&V) -> T + Clone + Send + Sync + 'static,
+) -> impl Fn(&V, &V) -> bool + Clone + Send + Sync + 'static {
+    move |a, b| projection(a) == projection(b)
+}
@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LockResult, Mutex};
+
+/// Error returned by the non-panicking methods of `HashMapComparer` and the other comparers
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ComparerError {
+    /// A thread panicked while updating the comparer and poison policy is `PoisonPolicy::Fail`
+    Poisoned,
+    /// Operation needs last hashmap, but it was never set
+    NotSeeded,
+    /// Last hashmap would have more keys than allowed by `HashMapComparer::with_max_keys()`
+    CapacityExceeded { limit: usize, len: usize },
+    /// Reading or writing a saved baseline failed
+    Io {
+        kind: io::ErrorKind,
+        message: String,
+    },
+    /// Saved baseline is truncated, damaged or not a saved baseline at all
+    Corrupt(String),
+    /// Saved baseline was written in a format version this version of the crate can't read
+    UnsupportedVersion(u16),
+    /// Key, value, string or vector is too long to be saved, lengths are stored as u32
+    TooLong(usize),
+    /// JSON or JSON Patch document is malformed or can't be applied
+    InvalidPatch(String),
+    /// Generation is neither the current one nor kept in history, so changes since it can't be computed.
+    /// Read last hashmap again to resync
+    GenerationNotRetained(u64),
+}
+
+impl fmt::Display for ComparerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComparerError::Poisoned => {
+                write!(
+                    f,
+                    "comparer is poisoned by a thread that panicked while updating it"
+                )
+            }
+            ComparerError::NotSeeded => write!(f, "last hashmap was never set"),
+            ComparerError::CapacityExceeded { limit, len } => {
+                write!(
+                    f,
+                    "hashmap has {len} keys, but comparer allows at most {limit}"
+                )
+            }
+            ComparerError::Io { message, .. } => write!(f, "io error: {message}"),
+            ComparerError::Corrupt(reason) => write!(f, "saved baseline is corrupt: {reason}"),
+            ComparerError::UnsupportedVersion(version) => {
+                write!(f, "saved baseline has unsupported format version {version}")
+            }
+            ComparerError::TooLong(len) => {
+                write!(
+                    f,
+                    "length {len} is too long to be saved, at most {} is supported",
+                    u32::MAX
+                )
+            }
+            ComparerError::InvalidPatch(reason) => write!(f, "invalid patch: {reason}"),
+            ComparerError::GenerationNotRetained(generation) => {
+                write!(f, "generation {generation} is not kept in history")
+            }
+        }
+    }
+}
+
+impl Error for ComparerError {}
+
+impl From<io::Error> for ComparerError {
+    fn from(error: io::Error) -> Self {
+        ComparerError::Io {
+            kind: error.kind(),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// What comparer does after a thread panicked while updating it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PoisonPolicy {
+    /// Every following update fails, panicking methods panic and `try_` methods return `ComparerError::Poisoned`
+    #[default]
+    Fail,
+    /// Lock is cleared and updates continue from last hashmap, which the panicking update never replaced
+    Recover,
+}
+
+impl PoisonPolicy {
+    /// Unwraps a lock result, clearing the poison or failing depending on the policy
+    pub(crate) fn recover<T, G>(
+        self,
+        mutex: &Mutex<T>,
+        result: LockResult<G>,
+    ) -> Result<G, ComparerError> {
+        result.or_else(|poisoned| match self {
+            PoisonPolicy::Fail => Err(ComparerError::Poisoned),
+            PoisonPolicy::Recover => {
+                mutex.clear_poison();
+                Ok(poisoned.into_inner())
+            }
+        })
+    }
+}
+
+/// Poison policy shared by every handle of a comparer
+#[derive(Clone, Default)]
+pub(crate) struct SharedPoisonPolicy {
+    recover: Arc<AtomicBool>,
+}
+
+impl SharedPoisonPolicy {
+    /// Independent copy, e.g. for a fork
+    pub(crate) fn detach(&self) -> Self {
+        let detached = Self::default();
+        detached.set(self.get());
+        detached
+    }
+
+    pub(crate) fn get(&self) -> PoisonPolicy {
+        if self.recover.load(Ordering::Relaxed) {
+            PoisonPolicy::Recover
+        } else {
+            PoisonPolicy::Fail
+        }
+    }
+
+    pub(crate) fn set(&self, poison_policy: PoisonPolicy) {
+        let recover = poison_policy == PoisonPolicy::Recover;
+        self.recover.store(recover, Ordering::Relaxed);
+    }
+
+    pub(crate) fn recover<T, G>(
+        &self,
+        mutex: &Mutex<T>,
+        result: LockResult<G>,
+    ) -> Result<G, ComparerError> {
+        self.get().recover(mutex, result)
+    }
+}
+
+impl fmt::Debug for SharedPoisonPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.get().fmt(f)
+    }
+}
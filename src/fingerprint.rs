@@ -0,0 +1,280 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash};
+use std::marker::PhantomData;
+
+use crate::shared::{Last, Shared};
+use crate::{or_panic, ComparerError, MapLike, PoisonPolicy};
+
+/// Keys that changed between last hashmap and a new one, returned by `FingerprintComparer::diff_keys()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyChanges<K> {
+    /// Keys that exist only in the new hashmap
+    pub added: Vec<K>,
+    /// Keys that exist only in the last hashmap
+    pub removed: Vec<K>,
+    /// Keys whose value digest changed
+    pub modified: Vec<K>,
+}
+
+impl<K> KeyChanges<K> {
+    /// Creates empty key changes
+    pub fn new() -> Self {
+        Self {
+            added: Vec::new(),
+            removed: Vec::new(),
+            modified: Vec::new(),
+        }
+    }
+
+    /// Returns true if no key changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+impl<K> Default for KeyChanges<K> {
+    fn default() -> Self {
+        KeyChanges::new()
+    }
+}
+
+/// Comparer that remembers only a 64 bit digest of every value instead of a copy of last hashmap
+///
+/// Updating costs hashing every value once and memory for the keys and their digests,
+/// which is much cheaper than cloning when values are large. Values only have to implement `Hash`.
+///
+/// The tradeoff is that two different values with the same digest are treated as the same value,
+/// so a change can be missed when digests collide. With the default 64 bit hasher this is very unlikely
+/// for accidental changes, but it shouldn't be relied on against values crafted to collide.
+/// Digests are only comparable within the same process and hasher.
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::FingerprintComparer;
+///
+///   let comparer = FingerprintComparer::<u8, Vec<u8>>::new();
+///   let mut blobs = HashMap::from([(1, vec![0; 4096]), (2, vec![1; 4096])]);
+///   assert_eq!(blobs, comparer.update_and_compare(&blobs).unwrap());
+///   assert!(comparer.is_same(&blobs));
+///
+///   blobs.get_mut(&2).unwrap()[100] = 7;
+///   blobs.remove(&1);
+///   let changes = comparer.diff_keys(&blobs);
+///   assert_eq!(vec![1], changes.removed);
+///   assert_eq!(vec![2], changes.modified);
+///   assert_eq!(vec![2], comparer.update_and_compare(&blobs).unwrap().into_keys().collect::<Vec<_>>());
+/// ```
+/// Colliding digests hide changes:
+/// ```
+///   use std::collections::HashMap;
+///   use std::hash::{BuildHasherDefault, Hasher};
+///   use comparer::FingerprintComparer;
+///
+///   // Hasher that gives every value the same digest
+///   #[derive(Default)]
+///   struct Colliding;
+///   impl Hasher for Colliding {
+///       fn finish(&self) -> u64 { 0 }
+///       fn write(&mut self, _: &[u8]) {}
+///   }
+///
+///   let comparer = FingerprintComparer::<u8, &str, _>::with_hasher(BuildHasherDefault::<Colliding>::default());
+///   comparer.update(&HashMap::from([(1, "foo")]));
+///   assert!(comparer.is_same(&HashMap::from([(1, "bar")])));
+///   assert!(comparer.diff_keys(&HashMap::from([(1, "bar")])).is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct FingerprintComparer<K, V, S = BuildHasherDefault<DefaultHasher>> {
+    fingerprints: Shared<Last<Fingerprints<K>>>,
+    hasher: S,
+    value: PhantomData<fn(&V)>,
+}
+
+#[derive(Debug)]
+struct Fingerprints<K> {
+    digests: HashMap<K, u64>,
+    map_digest: u64,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + Hash> FingerprintComparer<K, V> {
+    pub fn new() -> Self {
+        Self::with_hasher(BuildHasherDefault::default())
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + Hash, S: BuildHasher> FingerprintComparer<K, V, S> {
+    /// Creates comparer that computes digests with the given hasher
+    pub fn with_hasher(hasher: S) -> Self {
+        Self {
+            fingerprints: Shared::new(Last::default()),
+            hasher,
+            value: PhantomData,
+        }
+    }
+
+    /// Sets what happens after a thread panicked while updating the comparer, e.g. in `Hash` of a value,
+    /// see `HashMapComparer::with_poison_policy()`
+    pub fn with_poison_policy(self, poison_policy: PoisonPolicy) -> Self {
+        self.fingerprints.set_poison_policy(poison_policy);
+        self
+    }
+
+    /// Digest of the whole last hashmap, doesn't depend on the order of keys
+    pub fn map_digest(&self) -> u64 {
+        or_panic(self.try_map_digest())
+    }
+
+    /// Same as `map_digest()`, but returns an error instead of panicking
+    pub fn try_map_digest(&self) -> Result<u64, ComparerError> {
+        self.fingerprints.read(|last| last.map_digest)
+    }
+
+    /// Checks if last hashmap is the same as new one by comparing whole map digests
+    pub fn is_same<M: MapLike<K, V>>(&self, comparable: &M) -> bool {
+        or_panic(self.try_is_same(comparable))
+    }
+
+    /// Same as `is_same()`, but returns an error instead of panicking
+    pub fn try_is_same<M: MapLike<K, V>>(&self, comparable: &M) -> Result<bool, ComparerError> {
+        self.fingerprints.read(|last| self.same(last, comparable))
+    }
+
+    /// Updates digests of last hashmap
+    pub fn update<M: MapLike<K, V>>(&self, new_map: &M) {
+        or_panic(self.try_update(new_map))
+    }
+
+    /// Same as `update()`, but returns an error instead of panicking
+    pub fn try_update<M: MapLike<K, V>>(&self, new_map: &M) -> Result<(), ComparerError> {
+        let digests = new_map
+            .iter()
+            .map(|(key, value)| (key.clone(), self.hasher.hash_one(value)))
+            .collect();
+        let fingerprints = Fingerprints::new(digests, &self.hasher);
+        self.fingerprints.update(|_| ((), fingerprints))
+    }
+
+    /// Checks if last hashmap is the same as new one and updates it to be that new value
+    pub fn is_same_update<M: MapLike<K, V>>(&self, new_map: &M) -> bool {
+        or_panic(self.try_is_same_update(new_map))
+    }
+
+    /// Same as `is_same_update()`, but returns an error instead of panicking
+    pub fn try_is_same_update<M: MapLike<K, V>>(&self, new_map: &M) -> Result<bool, ComparerError> {
+        let digests = new_map
+            .iter()
+            .map(|(key, value)| (key.clone(), self.hasher.hash_one(value)))
+            .collect();
+        let fingerprints = Fingerprints::new(digests, &self.hasher);
+        self.fingerprints.update(|last| {
+            let is_same = last.digests.len() == fingerprints.digests.len()
+                && last.map_digest == fingerprints.map_digest;
+            (is_same, fingerprints)
+        })
+    }
+
+    /// Compares new hashmap to the last one and returns added and modified values.
+    /// Only changed values are cloned. The first comparison returns the whole hashmap.
+    pub fn compare<M: MapLike<K, V>>(&self, new_map: &M) -> Result<HashMap<K, V>, ComparerError> {
+        self.fingerprints.read(|last| {
+            new_map
+                .iter()
+                .filter(|(key, value)| last.digests.get(*key) != Some(&self.hasher.hash_one(value)))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        })
+    }
+
+    /// Updates digests of last hashmap and returns added and modified values, see `compare()`
+    pub fn update_and_compare<M: MapLike<K, V>>(
+        &self,
+        new_map: &M,
+    ) -> Result<HashMap<K, V>, ComparerError> {
+        self.fingerprints.update(|last| {
+            let mut changed_values = HashMap::new();
+            let mut digests = HashMap::with_capacity(new_map.len());
+            for (key, value) in new_map.iter() {
+                let digest = self.hasher.hash_one(value);
+                if last.digests.get(key) != Some(&digest) {
+                    changed_values.insert(key.clone(), value.clone());
+                }
+                digests.insert(key.clone(), digest);
+            }
+            (changed_values, Fingerprints::new(digests, &self.hasher))
+        })
+    }
+
+    /// Compares new hashmap to the last one and returns keys that were added, removed or modified.
+    /// Nothing is cloned except the changed keys.
+    pub fn diff_keys<M: MapLike<K, V>>(&self, new_map: &M) -> KeyChanges<K> {
+        or_panic(self.try_diff_keys(new_map))
+    }
+
+    /// Same as `diff_keys()`, but returns an error instead of panicking
+    pub fn try_diff_keys<M: MapLike<K, V>>(
+        &self,
+        new_map: &M,
+    ) -> Result<KeyChanges<K>, ComparerError> {
+        self.fingerprints.read(|last| {
+            let mut changes = KeyChanges::new();
+            for (key, value) in new_map.iter() {
+                match last.digests.get(key) {
+                    None => changes.added.push(key.clone()),
+                    Some(digest) if *digest != self.hasher.hash_one(value) => {
+                        changes.modified.push(key.clone())
+                    }
+                    Some(_) => {}
+                }
+            }
+            for key in last.digests.keys() {
+                if !new_map.contains_key(key) {
+                    changes.removed.push(key.clone());
+                }
+            }
+            changes
+        })
+    }
+
+    fn same(&self, last: &Fingerprints<K>, comparable: &impl MapLike<K, V>) -> bool {
+        last.digests.len() == comparable.len() && last.map_digest == self.map_digest_of(comparable)
+    }
+
+    fn map_digest_of(&self, map: &impl MapLike<K, V>) -> u64 {
+        map.iter().fold(0, |digest, (key, value)| {
+            digest.wrapping_add(entry_digest(&self.hasher, key, self.hasher.hash_one(value)))
+        })
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + Hash> Default for FingerprintComparer<K, V> {
+    fn default() -> Self {
+        FingerprintComparer::new()
+    }
+}
+
+impl<K> Default for Fingerprints<K> {
+    fn default() -> Self {
+        Self {
+            digests: HashMap::new(),
+            map_digest: 0,
+        }
+    }
+}
+
+impl<K: Hash> Fingerprints<K> {
+    fn new(digests: HashMap<K, u64>, hasher: &impl BuildHasher) -> Self {
+        let map_digest = digests.iter().fold(0u64, |digest, (key, value_digest)| {
+            digest.wrapping_add(entry_digest(hasher, key, *value_digest))
+        });
+        Self {
+            digests,
+            map_digest,
+        }
+    }
+}
+
+/// Digest of a key and its value digest, summed up into an order independent digest of the whole map
+fn entry_digest<K: Hash>(hasher: &impl BuildHasher, key: &K, value_digest: u64) -> u64 {
+    hasher.hash_one((key, value_digest))
+}
@@ -0,0 +1,643 @@
+//! Minimal JSON support for exchanging changes as RFC 6902 JSON Patch documents
+//!
+//! `ChangeSet::to_json_patch()` writes changes as `add`, `remove` and `replace` operations,
+//! `apply_patch()` applies such a document onto a `HashMap`. Every key is a member of the root object,
+//! its path is the key formatted with `Display` and escaped as a JSON Pointer, so `a/b` becomes `/a~1b`.
+//! Values are converted with `ToJson` and `FromJson`.
+//! # Examples
+//! ```
+//!   use std::collections::HashMap;
+//!   use comparer::{json, HashMapComparer};
+//!
+//!   let comparer = HashMapComparer::<String, u32>::new();
+//!   let mut server = HashMap::from([("workers".to_string(), 4), ("a/b".to_string(), 1)]);
+//!   comparer.update(&server);
+//!   let mut client = server.clone();
+//!
+//!   server.insert("workers".to_string(), 8);
+//!   server.remove("a/b");
+//!   let patch = comparer.update_and_diff(&server).to_json_patch();
+//!   assert!(patch.contains(r#"{"op":"remove","path":"/a~1b"}"#));
+//!   assert!(patch.contains(r#"{"op":"replace","path":"/workers","value":8}"#));
+//!
+//!   json::apply_patch(&mut client, &patch).unwrap();
+//!   assert_eq!(server, client);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Write};
+use std::hash::Hash;
+use std::str::FromStr;
+
+use crate::{Change, ChangeSet, ComparerError};
+
+/// JSON value
+///
+/// Numbers keep their text, so integers of any size convert without losing precision.
+/// Objects keep the order of their members.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Converts a value to JSON
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+/// Converts JSON back to a value
+pub trait FromJson: Sized {
+    fn from_json(json: &Json) -> Result<Self, ComparerError>;
+}
+
+impl Json {
+    /// Parses a JSON document
+    /// # Examples
+    /// ```
+    ///   use comparer::json::Json;
+    ///
+    ///   let json = Json::parse(r#" {"name": "café", "tags": [1, 2.5, null]} "#).unwrap();
+    ///   assert_eq!(Some(&Json::String("café".to_string())), json.get("name"));
+    ///   assert_eq!(r#"{"name":"café","tags":[1,2.5,null]}"#, json.to_string());
+    ///   assert!(Json::parse("[1, 2").is_err());
+    ///
+    ///   assert_eq!(Json::String("A".to_string()), Json::parse(r#""\u0041""#).unwrap());
+    ///   assert!(Json::parse(r#""\u+041""#).is_err());
+    /// ```
+    pub fn parse(text: &str) -> Result<Json, ComparerError> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+            depth: 0,
+        };
+        let json = parser.value()?;
+        parser.whitespace();
+        if parser.position != parser.bytes.len() {
+            return Err(parser.error("trailing characters"));
+        }
+        Ok(json)
+    }
+
+    /// Returns member of an object, `None` for other values
+    pub fn get(&self, name: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(member, _)| member == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(value) => write!(f, "{value}"),
+            Json::Number(number) => f.write_str(number),
+            Json::String(string) => write_string(f, string),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, string: &str) -> fmt::Result {
+    f.write_char('"')?;
+    for c in string.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// Deeper documents are rejected instead of overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> ComparerError {
+        ComparerError::InvalidPatch(format!("{reason} at byte {}", self.position))
+    }
+
+    fn whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), ComparerError> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected `{}`", expected as char)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn literal(&mut self, literal: &str, json: Json) -> Result<Json, ComparerError> {
+        if !self.bytes[self.position..].starts_with(literal.as_bytes()) {
+            return Err(self.error("invalid literal"));
+        }
+        self.position += literal.len();
+        Ok(json)
+    }
+
+    fn value(&mut self) -> Result<Json, ComparerError> {
+        self.whitespace();
+        match self.peek() {
+            None => Err(self.error("unexpected end")),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b'[') => self.nested(Self::array),
+            Some(b'{') => self.nested(Self::object),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Json, ComparerError>,
+    ) -> Result<Json, ComparerError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+        self.depth += 1;
+        let json = parse(self);
+        self.depth -= 1;
+        json
+    }
+
+    fn array(&mut self) -> Result<Json, ComparerError> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected `,` or `]`")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, ComparerError> {
+        self.expect(b'{')?;
+        let mut members = Vec::new();
+        self.whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.whitespace();
+            let name = self.string()?;
+            self.whitespace();
+            self.expect(b':')?;
+            members.push((name, self.value()?));
+            self.whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(Json::Object(members));
+                }
+                _ => return Err(self.error("expected `,` or `}`")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, ComparerError> {
+        let start = self.position;
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.error("invalid number")),
+        }
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            self.required_digits()?;
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.position += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.position += 1;
+            }
+            self.required_digits()?;
+        }
+        let number = std::str::from_utf8(&self.bytes[start..self.position]).unwrap();
+        Ok(Json::Number(number.to_string()))
+    }
+
+    fn digits(&mut self) {
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn required_digits(&mut self) -> Result<(), ComparerError> {
+        let start = self.position;
+        self.digits();
+        if self.position == start {
+            return Err(self.error("invalid number"));
+        }
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String, ComparerError> {
+        self.expect(b'"')?;
+        let mut string = String::new();
+        loop {
+            let start = self.position;
+            while let Some(byte) = self.peek() {
+                if byte == b'"' || byte == b'\\' || byte < b' ' {
+                    break;
+                }
+                self.position += 1;
+            }
+            // Input is a `str` and the run stops only at ASCII bytes, so it is valid UTF-8
+            string.push_str(std::str::from_utf8(&self.bytes[start..self.position]).unwrap());
+            match self.peek() {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(string);
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    string.push(self.escape()?);
+                }
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, ComparerError> {
+        let escaped = self
+            .peek()
+            .ok_or_else(|| self.error("unterminated string"))?;
+        self.position += 1;
+        Ok(match escaped {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let high = self.hex()?;
+                if (0xd800..0xdc00).contains(&high) {
+                    self.expect(b'\\')?;
+                    self.expect(b'u')?;
+                    let low = self.hex()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error("invalid surrogate pair"));
+                    }
+                    let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                    char::from_u32(code).ok_or_else(|| self.error("invalid surrogate pair"))?
+                } else {
+                    char::from_u32(high).ok_or_else(|| self.error("invalid escape"))?
+                }
+            }
+            _ => return Err(self.error("invalid escape")),
+        })
+    }
+
+    fn hex(&mut self) -> Result<u32, ComparerError> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            // `from_str_radix` alone would also accept a sign like in `\u+041`
+            .filter(|digits| digits.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("invalid unicode escape"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+}
+
+impl ToJson for Json {
+    fn to_json(&self) -> Json {
+        self.clone()
+    }
+}
+
+impl FromJson for Json {
+    fn from_json(json: &Json) -> Result<Self, ComparerError> {
+        Ok(json.clone())
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Json {
+        Json::Bool(*self)
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(json: &Json) -> Result<Self, ComparerError> {
+        match json {
+            Json::Bool(value) => Ok(*value),
+            _ => Err(mismatch("bool", json)),
+        }
+    }
+}
+
+macro_rules! json_integer {
+    ($($integer:ty),*) => {
+        $(
+            impl ToJson for $integer {
+                fn to_json(&self) -> Json {
+                    Json::Number(self.to_string())
+                }
+            }
+
+            impl FromJson for $integer {
+                fn from_json(json: &Json) -> Result<Self, ComparerError> {
+                    match json {
+                        Json::Number(number) => number.parse().map_err(|_| mismatch(stringify!($integer), json)),
+                        _ => Err(mismatch(stringify!($integer), json)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+json_integer!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+macro_rules! json_float {
+    ($($float:ty),*) => {
+        $(
+            /// Infinite and NaN values have no JSON representation and are written as `null`
+            impl ToJson for $float {
+                fn to_json(&self) -> Json {
+                    if self.is_finite() {
+                        Json::Number(self.to_string())
+                    } else {
+                        Json::Null
+                    }
+                }
+            }
+
+            impl FromJson for $float {
+                fn from_json(json: &Json) -> Result<Self, ComparerError> {
+                    match json {
+                        Json::Number(number) => number.parse().map_err(|_| mismatch(stringify!($float), json)),
+                        _ => Err(mismatch(stringify!($float), json)),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+json_float!(f32, f64);
+
+impl ToJson for str {
+    fn to_json(&self) -> Json {
+        Json::String(self.to_string())
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::String(self.clone())
+    }
+}
+
+impl FromJson for String {
+    fn from_json(json: &Json) -> Result<Self, ComparerError> {
+        match json {
+            Json::String(string) => Ok(string.clone()),
+            _ => Err(mismatch("string", json)),
+        }
+    }
+}
+
+impl<T: ToJson + ?Sized> ToJson for &T {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, ToJson::to_json)
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(json: &Json) -> Result<Self, ComparerError> {
+        match json {
+            Json::Null => Ok(None),
+            _ => T::from_json(json).map(Some),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(json: &Json) -> Result<Self, ComparerError> {
+        match json {
+            Json::Array(items) => items.iter().map(T::from_json).collect(),
+            _ => Err(mismatch("array", json)),
+        }
+    }
+}
+
+fn mismatch(expected: &str, json: &Json) -> ComparerError {
+    ComparerError::InvalidPatch(format!("expected {expected}, found {json}"))
+}
+
+impl<K: Display, V: ToJson> ChangeSet<K, V> {
+    /// Writes changes as an RFC 6902 JSON Patch document. Added and initial keys become `add` operations,
+    /// removed keys `remove` and modified keys `replace`. See `json` module for how keys become paths.
+    pub fn to_json_patch(&self) -> String {
+        let operations = self
+            .iter()
+            .map(|(key, change)| {
+                let (op, value) = match change {
+                    Change::Added(new) | Change::Initial(new) => ("add", Some(new)),
+                    Change::Removed(_) => ("remove", None),
+                    Change::Modified { new, .. } => ("replace", Some(new)),
+                };
+                let mut operation = vec![
+                    ("op".to_string(), Json::String(op.to_string())),
+                    ("path".to_string(), Json::String(pointer(key))),
+                ];
+                if let Some(value) = value {
+                    operation.push(("value".to_string(), value.to_json()));
+                }
+                Json::Object(operation)
+            })
+            .collect();
+        Json::Array(operations).to_string()
+    }
+}
+
+/// JSON Pointer of a key in the root object
+fn pointer(key: &impl Display) -> String {
+    format!("/{}", key.to_string().replace('~', "~0").replace('/', "~1"))
+}
+
+/// Key addressed by a JSON Pointer into the root object
+fn key_of<K: FromStr>(path: &str) -> Result<K, ComparerError> {
+    let Some(segment) = path
+        .strip_prefix('/')
+        .filter(|segment| !segment.contains('/'))
+    else {
+        return Err(ComparerError::InvalidPatch(format!(
+            "path `{path}` doesn't address a key of the root object"
+        )));
+    };
+    segment
+        .replace("~1", "/")
+        .replace("~0", "~")
+        .parse()
+        .map_err(|_| ComparerError::InvalidPatch(format!("path `{path}` isn't a valid key")))
+}
+
+/// Applies an RFC 6902 JSON Patch document onto a hashmap. Supports `add`, `remove` and `replace`
+/// operations on keys of the root object, see `json` module for how paths become keys.
+///
+/// The patch is applied as a whole: if any operation fails, e.g. `remove` of a missing key,
+/// the hashmap is left unchanged and `ComparerError::InvalidPatch` is returned.
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::{json, ComparerError};
+///
+///   let mut map = HashMap::from([(1, "foo".to_string())]);
+///   let patch = r#"[{"op": "add", "path": "/2", "value": "bar"}, {"op": "remove", "path": "/3"}]"#;
+///   assert!(matches!(json::apply_patch(&mut map, patch), Err(ComparerError::InvalidPatch(_))));
+///   assert_eq!(HashMap::from([(1, "foo".to_string())]), map);
+/// ```
+pub fn apply_patch<K, V>(map: &mut HashMap<K, V>, patch: &str) -> Result<(), ComparerError>
+where
+    K: Clone + Eq + Hash + FromStr,
+    V: FromJson,
+{
+    let Json::Array(operations) = Json::parse(patch)? else {
+        return Err(ComparerError::InvalidPatch(
+            "patch has to be an array of operations".to_string(),
+        ));
+    };
+    let mut undo: Vec<(K, Option<V>)> = Vec::new();
+    for operation in &operations {
+        if let Err(error) = apply_operation(map, operation, &mut undo) {
+            for (key, previous) in undo.into_iter().rev() {
+                match previous {
+                    Some(value) => map.insert(key, value),
+                    None => map.remove(&key),
+                };
+            }
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+fn apply_operation<K, V>(
+    map: &mut HashMap<K, V>,
+    operation: &Json,
+    undo: &mut Vec<(K, Option<V>)>,
+) -> Result<(), ComparerError>
+where
+    K: Clone + Eq + Hash + FromStr,
+    V: FromJson,
+{
+    let member = |name: &str| {
+        operation.get(name).ok_or_else(|| {
+            ComparerError::InvalidPatch(format!("operation {operation} has no `{name}`"))
+        })
+    };
+    let (Json::String(op), Json::String(path)) = (member("op")?, member("path")?) else {
+        return Err(ComparerError::InvalidPatch(format!(
+            "operation {operation} has invalid `op` or `path`"
+        )));
+    };
+    let key: K = key_of(path)?;
+    let previous = match op.as_str() {
+        "add" => map.insert(key.clone(), V::from_json(member("value")?)?),
+        "replace" if map.contains_key(&key) => {
+            map.insert(key.clone(), V::from_json(member("value")?)?)
+        }
+        "remove" if map.contains_key(&key) => map.remove(&key),
+        "replace" | "remove" => {
+            return Err(ComparerError::InvalidPatch(format!(
+                "path `{path}` doesn't exist"
+            )))
+        }
+        _ => {
+            return Err(ComparerError::InvalidPatch(format!(
+                "unsupported operation `{op}`"
+            )))
+        }
+    };
+    undo.push((key, previous));
+    Ok(())
+}
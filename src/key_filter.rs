@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::{ChangeSet, MapLike};
+
+/// Decides which keys a comparer watches, see `HashMapComparer::with_key_filter()`
+///
+/// Ignored keys are never stored in last hashmap, never make hashmaps different
+/// and never appear in changes.
+#[derive(Clone)]
+pub struct KeyFilter<K> {
+    rule: Rule<K>,
+}
+
+#[derive(Clone)]
+enum Rule<K> {
+    All,
+    Include(Arc<HashSet<K>>),
+    Exclude(Arc<HashSet<K>>),
+    Matching(Arc<dyn Fn(&K) -> bool + Send + Sync>),
+}
+
+impl<K> KeyFilter<K> {
+    /// Watches every key
+    pub fn all() -> Self {
+        Self { rule: Rule::All }
+    }
+
+    /// Watches only keys the predicate returns true for
+    pub fn matching(predicate: impl Fn(&K) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            rule: Rule::Matching(Arc::new(predicate)),
+        }
+    }
+}
+
+impl<K: Eq + Hash> KeyFilter<K> {
+    /// Watches only the given keys
+    pub fn include(keys: impl IntoIterator<Item = K>) -> Self {
+        Self {
+            rule: Rule::Include(Arc::new(keys.into_iter().collect())),
+        }
+    }
+
+    /// Watches every key except the given ones
+    pub fn exclude(keys: impl IntoIterator<Item = K>) -> Self {
+        Self {
+            rule: Rule::Exclude(Arc::new(keys.into_iter().collect())),
+        }
+    }
+
+    /// Returns true if changes of the key are watched
+    pub fn allows(&self, key: &K) -> bool {
+        match &self.rule {
+            Rule::All => true,
+            Rule::Include(keys) => keys.contains(key),
+            Rule::Exclude(keys) => !keys.contains(key),
+            Rule::Matching(predicate) => predicate(key),
+        }
+    }
+
+    /// Returns view of a map that hides ignored keys
+    pub(crate) fn view<'a, V, M: MapLike<K, V>>(&'a self, map: &'a M) -> Filtered<'a, K, V, M> {
+        Filtered {
+            map,
+            filter: self,
+            value: PhantomData,
+        }
+    }
+}
+
+impl<K> Default for KeyFilter<K> {
+    fn default() -> Self {
+        KeyFilter::all()
+    }
+}
+
+impl<K: fmt::Debug> fmt::Debug for KeyFilter<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.rule {
+            Rule::All => f.write_str("KeyFilter::All"),
+            Rule::Include(keys) => f.debug_tuple("KeyFilter::Include").field(keys).finish(),
+            Rule::Exclude(keys) => f.debug_tuple("KeyFilter::Exclude").field(keys).finish(),
+            Rule::Matching(_) => f.write_str("KeyFilter::Matching(..)"),
+        }
+    }
+}
+
+/// Map with ignored keys hidden
+pub(crate) struct Filtered<'a, K, V, M> {
+    map: &'a M,
+    filter: &'a KeyFilter<K>,
+    value: PhantomData<V>,
+}
+
+impl<K: Eq + Hash, V, M: MapLike<K, V>> MapLike<K, V> for Filtered<'_, K, V, M> {
+    fn get(&self, key: &K) -> Option<&V> {
+        if self.filter.allows(key) {
+            self.map.get(key)
+        } else {
+            None
+        }
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.map.iter().filter(|(key, _)| self.filter.allows(key))
+    }
+
+    fn len(&self) -> usize {
+        match self.filter.rule {
+            Rule::All => self.map.len(),
+            _ => self.iter().count(),
+        }
+    }
+
+    fn order_changes(changes: &mut ChangeSet<K, V>) {
+        M::order_changes(changes);
+    }
+}
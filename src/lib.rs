@@ -1,32 +1,568 @@
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::fmt;
 use std::hash::Hash;
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Condvar, MutexGuard};
+use std::time::{Duration, Instant};
+
+use baseline::{Baseline, Settings};
+use shared::Shared;
+use subscribers::Subscribers;
+
+mod baseline;
+mod change;
+pub mod diff;
+pub mod eq;
+mod error;
+mod fingerprint;
+pub mod json;
+mod key_filter;
+mod local;
+mod map_like;
+pub mod persist;
+mod render;
+mod sequence;
+mod set;
+mod shared;
+mod snapshot;
+mod subscribers;
+mod tracked;
+
+pub use change::{Change, ChangeSet};
+/// Derives `Diff` for structs and enums, see `comparer_derive` crate
+#[cfg(feature = "derive")]
+pub use comparer_derive::Diff;
+pub use diff::Diff;
+pub use error::{ComparerError, PoisonPolicy};
+pub use fingerprint::{FingerprintComparer, KeyChanges};
+pub use key_filter::KeyFilter;
+pub use local::LocalComparer;
+pub use map_like::MapLike;
+pub use render::Render;
+pub use sequence::{Edit, VecComparer};
+pub use set::{HashSetComparer, MultisetComparer, SetChanges};
+pub use snapshot::Snapshot;
+pub use subscribers::Subscription;
+pub use tracked::TrackedMap;
+
+/// What comparer reports when it is compared for the first time, before any baseline was set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FirstRun {
+    /// Every key of the new hashmap is reported as added
+    #[default]
+    ReportAll,
+    /// Nothing is reported, the new hashmap only becomes the baseline
+    ReportNothing,
+    /// Every key of the new hashmap is reported as `Change::Initial` by `diff()` and `update_and_diff()`.
+    /// `compare()` and `update_and_compare()` return the whole hashmap like with `ReportAll`
+    Snapshot,
+}
+
+type Equality<V> = Arc<dyn Fn(&V, &V) -> bool + Send + Sync>;
 
 impl<K: Clone + Eq + Hash, V: Clone + PartialEq> HashMapComparer<K, V> {
     pub fn new() -> Self {
+        Self::with_eq(|a: &V, b: &V| a == b)
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> HashMapComparer<K, V> {
+    /// Creates comparer that uses a custom function instead of `==` to decide if two values are the same.
+    /// It is used by every comparison, including `is_same()`, `compare()`, `diff()` and their updating variants.
+    /// Values don't have to implement `PartialEq`. See `eq` module for ready made strategies.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   // Only report readings that moved by more than a whole degree
+    ///   let comparer = HashMapComparer::<&str, f64>::with_eq(|a, b| (a - b).abs() < 1.0);
+    ///   comparer.update(&HashMap::from([("kitchen", 21.2), ("garage", 12.0)]));
+    ///
+    ///   let changes = comparer.update_and_compare(&HashMap::from([("kitchen", 21.9), ("garage", 14.5)]));
+    ///   assert_eq!(HashMap::from([("garage", 14.5)]), changes.unwrap());
+    /// ```
+    pub fn with_eq(eq: impl Fn(&V, &V) -> bool + Send + Sync + 'static) -> Self {
+        Self::from_eq(Arc::new(eq))
+    }
+
+    pub(crate) fn from_eq(eq: Equality<V>) -> Self {
         Self {
-            last_map: Arc::new(Mutex::new(HashMap::new())),
+            baseline: Shared::new(Baseline::new()),
+            changed: Arc::new(Condvar::new()),
+            subscribers: Arc::new(Subscribers::new()),
+            eq,
         }
     }
 
+    /// Sets what is reported by the first comparison, before any baseline was set
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{Change, FirstRun, HashMapComparer};
+    ///
+    ///   let my_hashmap = HashMap::<u8, &str>::from_iter(vec![(1, "foo")]);
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new().with_first_run(FirstRun::ReportNothing);
+    ///   assert!(comparer.update_and_compare(&my_hashmap).unwrap().is_empty());
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new().with_first_run(FirstRun::Snapshot);
+    ///   let changes = comparer.update_and_diff(&my_hashmap);
+    ///   assert!(changes.is_initial());
+    ///   assert_eq!(Some(&Change::Initial("foo")), changes.get(&1));
+    /// ```
+    pub fn with_first_run(self, first_run: FirstRun) -> Self {
+        or_panic(self.try_with_first_run(first_run))
+    }
+
+    /// Same as `with_first_run()`, but returns an error instead of panicking
+    pub fn try_with_first_run(self, first_run: FirstRun) -> Result<Self, ComparerError> {
+        let writer = self.lock_writer()?;
+        self.lock()?.settings.first_run = first_run;
+        drop(writer);
+        Ok(self)
+    }
+
+    /// Makes comparer watch only keys allowed by the filter. Ignored keys are left out of last hashmap,
+    /// don't make `is_same()` return false and never appear in the results of comparisons or in notifications.
+    /// Keys the filter ignores are dropped from last hashmap, history and consumer cursors right away,
+    /// without counting as an update.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{HashMapComparer, KeyFilter};
+    ///
+    ///   let comparer = HashMapComparer::<&str, u64>::new()
+    ///       .with_key_filter(KeyFilter::exclude(["updated_at", "requests"]));
+    ///   let mut my_hashmap = HashMap::from([("workers", 4), ("updated_at", 1000), ("requests", 7)]);
+    ///   comparer.update(&my_hashmap);
+    ///   assert_eq!(HashMap::from([("workers", 4)]), comparer.clone_last());
+    ///
+    ///   my_hashmap.insert("updated_at", 1001);
+    ///   my_hashmap.insert("requests", 9);
+    ///   assert!(comparer.is_same_update(&my_hashmap));
+    ///
+    ///   my_hashmap.insert("workers", 8);
+    ///   assert_eq!(HashMap::from([("workers", 8)]), comparer.update_and_compare(&my_hashmap).unwrap());
+    ///
+    ///   let comparer = HashMapComparer::<&str, u64>::new()
+    ///       .with_key_filter(KeyFilter::matching(|key: &&str| key.starts_with("cpu.")));
+    ///   comparer.update(&HashMap::from([("cpu.load", 3), ("mem.free", 100)]));
+    ///   let changes = comparer.diff(&HashMap::from([("cpu.load", 3), ("mem.free", 80)]));
+    ///   assert!(changes.is_empty());
+    /// ```
+    /// Like every setting, the filter is shared by all handles of the comparer:
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{HashMapComparer, KeyFilter};
+    ///
+    ///   let comparer = HashMapComparer::<&str, u64>::new();
+    ///   comparer.update(&HashMap::from([("workers", 4), ("updated_at", 1000)]));
+    ///
+    ///   let shared = comparer.share().with_key_filter(KeyFilter::exclude(["updated_at"]));
+    ///   assert!(shared.is_same(&HashMap::from([("workers", 4)])));
+    ///   assert!(shared.diff(&HashMap::from([("workers", 4)])).is_empty());
+    ///   assert_eq!(HashMap::from([("workers", 4)]), comparer.clone_last());
+    ///   assert!(comparer.is_same(&HashMap::from([("workers", 4), ("updated_at", 1001)])));
+    /// ```
+    pub fn with_key_filter(self, key_filter: KeyFilter<K>) -> Self {
+        or_panic(self.try_with_key_filter(key_filter))
+    }
+
+    /// Same as `with_key_filter()`, but returns an error instead of panicking
+    pub fn try_with_key_filter(self, key_filter: KeyFilter<K>) -> Result<Self, ComparerError> {
+        let writer = self.lock_writer()?;
+        let replaced = self.lock()?.set_key_filter(key_filter);
+        drop((writer, replaced));
+        Ok(self)
+    }
+
+    /// Sets what happens after a thread panicked while updating the comparer,
+    /// e.g. in a custom equality function. By default every following update fails.
+    /// Readers aren't affected, because last hashmap is only swapped after the new one is complete.
+    /// The policy is shared by all handles of the comparer and can be changed even while it is poisoned.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use std::thread;
+    ///   use comparer::{ComparerError, HashMapComparer, PoisonPolicy};
+    ///
+    ///   let unlucky = |a: &u8, b: &u8| if *b == 13 { panic!("unlucky value") } else { a == b };
+    ///   let failing = HashMapComparer::<&str, u8>::with_eq(unlucky);
+    ///   let recovering = HashMapComparer::<&str, u8>::with_eq(unlucky).with_poison_policy(PoisonPolicy::Recover);
+    ///
+    ///   for comparer in [&failing, &recovering] {
+    ///       comparer.update(&HashMap::from([("foo", 1)]));
+    ///       let panicking = comparer.clone();
+    ///       let handle = thread::spawn(move || panicking.update_and_diff(&HashMap::from([("foo", 13)])));
+    ///       assert!(handle.join().is_err());
+    ///       assert_eq!(HashMap::from([("foo", 1)]), comparer.clone_last());
+    ///   }
+    ///
+    ///   assert_eq!(Err(ComparerError::Poisoned), failing.try_update(&HashMap::from([("foo", 2)])));
+    ///   assert_eq!(Ok(()), recovering.try_update(&HashMap::from([("foo", 2)])));
+    ///   assert!(recovering.is_same(&HashMap::from([("foo", 2)])));
+    /// ```
+    pub fn with_poison_policy(self, poison_policy: PoisonPolicy) -> Self {
+        self.baseline.set_poison_policy(poison_policy);
+        self
+    }
+
+    /// Limits how many keys last hashmap can have. Updates with more keys fail with
+    /// `ComparerError::CapacityExceeded` and leave last hashmap unchanged.
+    /// Only keys allowed by the key filter are counted. Last hashmap already stored isn't checked.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{ComparerError, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new().with_max_keys(2);
+    ///   assert!(comparer.try_update(&HashMap::from([(1, "foo"), (2, "bar")])).is_ok());
+    ///   assert_eq!(
+    ///       Err(ComparerError::CapacityExceeded { limit: 2, len: 3 }),
+    ///       comparer.update_and_compare(&HashMap::from([(1, "foo"), (2, "bar"), (3, "baz")]))
+    ///   );
+    ///   assert_eq!(2, comparer.clone_last().len());
+    /// ```
+    pub fn with_max_keys(self, max_keys: usize) -> Self {
+        or_panic(self.try_with_max_keys(max_keys))
+    }
+
+    /// Same as `with_max_keys()`, but returns an error instead of panicking
+    pub fn try_with_max_keys(self, max_keys: usize) -> Result<Self, ComparerError> {
+        let writer = self.lock_writer()?;
+        self.lock()?.settings.max_keys = Some(max_keys);
+        drop(writer);
+        Ok(self)
+    }
+
     /// Clones last hashmap
     pub fn clone_last(&self) -> HashMap<K, V> {
-        self.last_map.lock().unwrap().clone()
+        or_panic(self.try_clone_last())
     }
 
-    /// Checks if last hashmap is the same as new one
-    pub fn is_same(&self, comparable: &HashMap<K, V>) -> bool {
-        self.last_map.lock().unwrap().iter().eq(comparable)
+    /// Same as `clone_last()`, but returns an error instead of panicking
+    pub fn try_clone_last(&self) -> Result<HashMap<K, V>, ComparerError> {
+        Ok(HashMap::clone(&*self.try_snapshot()?))
+    }
+
+    /// Returns last hashmap without copying it. The snapshot is immutable and stays valid
+    /// while the comparer is updated, later updates swap in a new hashmap instead of changing this one.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   comparer.update(&HashMap::from([(1, "foo")]));
+    ///   let snapshot = comparer.snapshot();
+    ///
+    ///   comparer.update(&HashMap::from([(1, "bar")]));
+    ///   assert_eq!(Some(&"foo"), snapshot.get(&1));
+    ///   assert_eq!(Some(&"bar"), comparer.snapshot().get(&1));
+    /// ```
+    pub fn snapshot(&self) -> Arc<HashMap<K, V>> {
+        or_panic(self.try_snapshot())
+    }
+
+    /// Same as `snapshot()`, but returns an error instead of panicking
+    pub fn try_snapshot(&self) -> Result<Arc<HashMap<K, V>>, ComparerError> {
+        Ok(self.lock()?.map.clone())
+    }
+
+    /// Returns a handle to the same comparer, same as `clone()` but with the intent spelled out.
+    /// Every handle sees the same last hashmap, history, subscribers and consumers.
+    pub fn share(&self) -> Self {
+        self.clone()
+    }
+
+    /// Creates an independent comparer seeded with last hashmap, its generation and history.
+    /// The fork starts with a copy of the settings, but without subscribers or consumers.
+    /// Updates of the fork and of the original don't affect each other.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{Change, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   comparer.update(&HashMap::from([(1, "foo"), (2, "bar")]));
+    ///
+    ///   let shared = comparer.share();
+    ///   let fork = comparer.fork();
+    ///   assert_eq!(1, fork.generation());
+    ///
+    ///   fork.update(&HashMap::from([(1, "foo"), (2, "baz")]));
+    ///   shared.update(&HashMap::from([(1, "foo")]));
+    ///   assert_eq!(HashMap::from([(1, "foo")]), comparer.clone_last());
+    ///
+    ///   let changes = fork.diff_against(&comparer);
+    ///   assert_eq!(Some(&Change::Added("baz")), changes.get(&2));
+    ///   assert_eq!(1, changes.len());
+    /// ```
+    pub fn fork(&self) -> Self {
+        or_panic(self.try_fork())
+    }
+
+    /// Same as `fork()`, but returns an error instead of panicking
+    pub fn try_fork(&self) -> Result<Self, ComparerError> {
+        let baseline = self.lock()?.fork();
+        Ok(Self {
+            baseline: self.baseline.fork(baseline),
+            changed: Arc::new(Condvar::new()),
+            subscribers: Arc::new(Subscribers::new()),
+            eq: self.eq.clone(),
+        })
+    }
+
+    /// Returns changes that lead from last hashmap of `other` to last hashmap of this comparer,
+    /// e.g. to see how two forks diverged. Values are compared with this comparer's equality function.
+    pub fn diff_against(&self, other: &Self) -> ChangeSet<K, V> {
+        or_panic(self.try_diff_against(other))
+    }
+
+    /// Same as `diff_against()`, but returns an error instead of panicking
+    pub fn try_diff_against(&self, other: &Self) -> Result<ChangeSet<K, V>, ComparerError> {
+        let theirs = other.try_snapshot()?;
+        let ours = self.try_snapshot()?;
+        Ok(change::diff_maps(theirs.as_ref(), ours.as_ref(), &*self.eq))
+    }
+
+    /// Returns true if last hashmap was set at least once since the comparer was created or reset.
+    /// An empty hashmap passed to `update()` still counts as a baseline.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   assert!(!comparer.is_seeded());
+    ///
+    ///   comparer.update(&HashMap::new());
+    ///   assert!(comparer.is_seeded());
+    ///   // Baseline is empty but seeded, so only the new key is reported
+    ///   let my_hashmap = HashMap::<u8, &str>::from_iter(vec![(1, "foo")]);
+    ///   assert_eq!(my_hashmap, comparer.update_and_compare(&my_hashmap).unwrap());
+    ///
+    ///   comparer.reset();
+    ///   assert!(!comparer.is_seeded());
+    ///   assert!(comparer.clone_last().is_empty());
+    /// ```
+    pub fn is_seeded(&self) -> bool {
+        or_panic(self.try_is_seeded())
+    }
+
+    /// Same as `is_seeded()`, but returns an error instead of panicking
+    pub fn try_is_seeded(&self) -> Result<bool, ComparerError> {
+        Ok(self.lock()?.seeded)
+    }
+
+    /// Clears last hashmap, next comparison is treated as the first one again.
+    /// Reset counts as an update, so it starts a new generation with an empty hashmap.
+    pub fn reset(&self) {
+        or_panic(self.try_reset())
+    }
+
+    /// Same as `reset()`, but returns an error instead of panicking
+    pub fn try_reset(&self) -> Result<(), ComparerError> {
+        let writer = self.lock_writer()?;
+        let dropped = self.lock()?.replace(HashMap::new(), false);
+        drop(dropped);
+        self.updated(writer, None);
+        Ok(())
+    }
+
+    /// Keeps up to `depth` older generations of last hashmap besides the current one,
+    /// so they can be accessed with `snapshot_at()`, `value_at()` and `diff_generations()`.
+    /// History is shared by every clone of the comparer and is empty by default.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{Change, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new().with_history(2);
+    ///   assert_eq!(0, comparer.generation());
+    ///
+    ///   let mut my_hashmap = HashMap::<u8, &str>::new();
+    ///   for value in ["foo", "bar", "baz"] {
+    ///       my_hashmap.insert(1, value);
+    ///       comparer.update(&my_hashmap);
+    ///   }
+    ///   assert_eq!(3, comparer.generation());
+    ///   // Generation 0 doesn't fit into the history anymore
+    ///   assert_eq!(1, comparer.oldest_generation());
+    ///   assert_eq!(None, comparer.snapshot_at(0));
+    ///
+    ///   assert_eq!(Some(HashMap::from([(1, "foo")])), comparer.snapshot_at(1));
+    ///   assert_eq!(Some(Some("bar")), comparer.value_at(2, &1));
+    ///   assert_eq!(Some(None), comparer.value_at(2, &2));
+    ///
+    ///   let changes = comparer.diff_generations(1, 3).unwrap();
+    ///   assert_eq!(Some(&Change::Modified { old: "foo", new: "baz" }), changes.get(&1));
+    /// ```
+    pub fn with_history(self, depth: usize) -> Self {
+        or_panic(self.try_with_history(depth))
+    }
+
+    /// Same as `with_history()`, but returns an error instead of panicking
+    pub fn try_with_history(self, depth: usize) -> Result<Self, ComparerError> {
+        let dropped = self.lock()?.set_history_depth(depth);
+        drop(dropped);
+        Ok(self)
+    }
+
+    /// Generation of last hashmap, increased by one with every update. Comparer starts at generation 0
+    pub fn generation(&self) -> u64 {
+        or_panic(self.try_generation())
+    }
+
+    /// Same as `generation()`, but returns an error instead of panicking
+    pub fn try_generation(&self) -> Result<u64, ComparerError> {
+        Ok(self.lock()?.generation)
+    }
+
+    /// Oldest generation that is still kept in history
+    pub fn oldest_generation(&self) -> u64 {
+        or_panic(self.try_oldest_generation())
+    }
+
+    /// Same as `oldest_generation()`, but returns an error instead of panicking
+    pub fn try_oldest_generation(&self) -> Result<u64, ComparerError> {
+        Ok(self.lock()?.oldest_generation())
+    }
+
+    /// Clones hashmap of a generation, `None` if the generation isn't kept in history
+    pub fn snapshot_at(&self, generation: u64) -> Option<HashMap<K, V>> {
+        or_panic(self.try_snapshot_at(generation))
+    }
+
+    /// Same as `snapshot_at()`, but returns an error instead of panicking
+    pub fn try_snapshot_at(&self, generation: u64) -> Result<Option<HashMap<K, V>>, ComparerError> {
+        let snapshot = self.lock()?.snapshot(generation).cloned();
+        Ok(snapshot.map(|map| HashMap::clone(&map)))
+    }
+
+    /// Returns value a key had in a generation, `None` if the generation isn't kept in history
+    /// and `Some(None)` if the key didn't exist in that generation
+    pub fn value_at(&self, generation: u64, key: &K) -> Option<Option<V>> {
+        or_panic(self.try_value_at(generation, key))
+    }
+
+    /// Same as `value_at()`, but returns an error instead of panicking
+    pub fn try_value_at(
+        &self,
+        generation: u64,
+        key: &K,
+    ) -> Result<Option<Option<V>>, ComparerError> {
+        let snapshot = self.lock()?.snapshot(generation).cloned();
+        Ok(snapshot.map(|map| map.get(key).cloned()))
+    }
+
+    /// Returns changes that lead from generation `from` to generation `to`,
+    /// `None` if either of them isn't kept in history
+    pub fn diff_generations(&self, from: u64, to: u64) -> Option<ChangeSet<K, V>> {
+        or_panic(self.try_diff_generations(from, to))
+    }
+
+    /// Same as `diff_generations()`, but returns an error instead of panicking
+    pub fn try_diff_generations(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> Result<Option<ChangeSet<K, V>>, ComparerError> {
+        let baseline = self.lock()?;
+        let (Some(from), Some(to)) = (
+            baseline.snapshot(from).cloned(),
+            baseline.snapshot(to).cloned(),
+        ) else {
+            return Ok(None);
+        };
+        drop(baseline);
+        Ok(Some(change::diff_maps(
+            from.as_ref(),
+            to.as_ref(),
+            &*self.eq,
+        )))
+    }
+
+    /// Checks if last hashmap is the same as new one.
+    /// Hashmaps are the same if they contain the same keys with equal values,
+    /// no matter in which order they were filled or how much capacity they have.
+    /// Stops at the first length mismatch or differing key and doesn't allocate.
+    /// Compares against a snapshot of last hashmap, so it doesn't wait for a running update.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let comparer = HashMapComparer::<u16, u16>::new();
+    ///   let forward = HashMap::<u16, u16>::from_iter((0..100).map(|i| (i, i * 2)));
+    ///   comparer.update(&forward);
+    ///
+    ///   // Same values inserted in reverse order into a hashmap with a different capacity
+    ///   let mut backward = HashMap::<u16, u16>::with_capacity(1000);
+    ///   for i in (0..100).rev() {
+    ///       backward.insert(i, i * 2);
+    ///   }
+    ///   assert!(comparer.is_same(&backward));
+    ///
+    ///   // Rehashed by growing and shrinking again
+    ///   for i in 100..1000 {
+    ///       backward.insert(i, 0);
+    ///   }
+    ///   backward.retain(|key, _| *key < 100);
+    ///   backward.shrink_to_fit();
+    ///   assert!(comparer.is_same(&backward));
+    /// ```
+    /// Different lengths, keys or values are never the same:
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   assert!(comparer.is_same(&HashMap::new()));
+    ///
+    ///   comparer.update(&HashMap::from([(1, "foo"), (2, "bar")]));
+    ///   assert!(!comparer.is_same(&HashMap::from([(1, "foo")])));
+    ///   assert!(!comparer.is_same(&HashMap::from([(1, "foo"), (2, "bar"), (3, "baz")])));
+    ///   assert!(!comparer.is_same(&HashMap::from([(1, "foo"), (3, "bar")])));
+    ///   assert!(!comparer.is_same(&HashMap::from([(1, "foo"), (2, "baz")])));
+    ///   assert!(comparer.is_same(&HashMap::from([(2, "bar"), (1, "foo")])));
+    /// ```
+    pub fn is_same<M: MapLike<K, V>>(&self, comparable: &M) -> bool {
+        or_panic(self.try_is_same(comparable))
+    }
+
+    /// Same as `is_same()`, but returns an error instead of panicking
+    pub fn try_is_same<M: MapLike<K, V>>(&self, comparable: &M) -> Result<bool, ComparerError> {
+        let (last, _, settings) = self.current()?;
+        let comparable = &settings.key_filter.view(comparable);
+        Ok(change::same_maps(last.as_ref(), comparable, &*self.eq))
     }
 
     /// Updates last hashmap to a new value
-    pub fn update(&self, new_map: &HashMap<K, V>) {
-        self.last_map.lock().unwrap().clone_from(new_map);
+    pub fn update<M: MapLike<K, V>>(&self, new_map: &M) {
+        or_panic(self.try_update(new_map))
+    }
+
+    /// Same as `update()`, but returns an error instead of panicking
+    pub fn try_update<M: MapLike<K, V>>(&self, new_map: &M) -> Result<(), ComparerError> {
+        let writer = self.lock_writer()?;
+        let (last, seeded, settings) = self.current()?;
+        let new_map = &settings.key_filter.view(new_map);
+        settings.check_capacity(new_map.len())?;
+        let changes = self
+            .subscribers
+            .is_active()
+            .then(|| self.changes(&last, seeded, settings.first_run, new_map));
+        drop(last);
+        self.swap(new_map)?;
+        self.updated(writer, changes);
+        Ok(())
     }
 
     /// Checks if last hashmap is the same as new one and updates it to be that new value
-    /// You can access last hashmap using `clone_last()` or by locking last_map yourself
+    /// You can access last hashmap using `clone_last()`
     /// # Examples
     /// ```
     ///   use std::collections::HashMap;
@@ -42,13 +578,18 @@ impl<K: Clone + Eq + Hash, V: Clone + PartialEq> HashMapComparer<K, V> {
     ///   my_hashmap.insert(2, "bar");
     ///   // HashMap has new values
     ///   assert_eq!(false, comparer.is_same_update(&my_hashmap));
-
+    ///
     ///```
     ///
-    pub fn is_same_update(&self, new_map: &HashMap<K, V>) -> bool {
-        let is_same = self.is_same(new_map);
-        self.update(new_map);
-        is_same
+    pub fn is_same_update<M: MapLike<K, V>>(&self, new_map: &M) -> bool {
+        or_panic(self.try_is_same_update(new_map))
+    }
+
+    /// Same as `is_same_update()`, but returns an error instead of panicking
+    pub fn try_is_same_update<M: MapLike<K, V>>(&self, new_map: &M) -> Result<bool, ComparerError> {
+        let is_same = self.try_is_same(new_map)?;
+        self.try_update(new_map)?;
+        Ok(is_same)
     }
     /// Updates last hashmap, compares new one to the last one and returns changed values.
     /// If you want to compare hashmap without updating last hashmap use` compare()`.
@@ -60,18 +601,18 @@ impl<K: Clone + Eq + Hash, V: Clone + PartialEq> HashMapComparer<K, V> {
     ///   use comparer::HashMapComparer;
     ///   let comparer = HashMapComparer::<u8, &str>::new();
     ///   let mut my_hashmap = HashMap::<u8, &str>::new();
-
+    ///
     ///   my_hashmap.insert(1, "foo");
     ///   my_hashmap.insert(2, "bar");
     ///   my_hashmap.insert(4, "foo");
-
+    ///
     ///   let mut results: Vec<HashMap<u8, &str>> = vec![];
-
+    ///
     ///   for i in 0..5 {
     ///       my_hashmap.insert(i, "foo");
     ///       results.push(comparer.update_and_compare(&my_hashmap).unwrap());
     ///   }
-
+    ///
     ///   assert_eq!(
     ///       vec![
     ///           // In the first comparison comparer always returns whole hashmap because all values in it is new
@@ -88,24 +629,23 @@ impl<K: Clone + Eq + Hash, V: Clone + PartialEq> HashMapComparer<K, V> {
     ///       results
     ///   );
     /// ```
-
-    pub fn update_and_compare(
+    pub fn update_and_compare<M: MapLike<K, V>>(
         &self,
-        new_map: &HashMap<K, V>,
-    ) -> Result<HashMap<K, V>, Box<dyn std::error::Error>> {
-        let mut last_map = self.last_map.lock().unwrap();
-        let mut changed_values: HashMap<K, V> = HashMap::new();
-        if !last_map.is_empty() {
-            for (key, value) in new_map {
-                if !last_map.contains_key(key) || value != last_map.get(key).expect("None Value") {
-                    changed_values.insert(key.clone(), value.clone());
-                }
-            }
-            last_map.clone_from(new_map);
-            return Ok(changed_values);
-        }
-        last_map.clone_from(new_map);
-        Ok(new_map.clone())
+        new_map: &M,
+    ) -> Result<HashMap<K, V>, ComparerError> {
+        let writer = self.lock_writer()?;
+        let (last, seeded, settings) = self.current()?;
+        let new_map = &settings.key_filter.view(new_map);
+        settings.check_capacity(new_map.len())?;
+        let changed_values = self.changed_values(&last, seeded, settings.first_run, new_map);
+        let changes = self
+            .subscribers
+            .is_active()
+            .then(|| self.changes(&last, seeded, settings.first_run, new_map));
+        drop(last);
+        self.swap(new_map)?;
+        self.updated(writer, changes);
+        Ok(changed_values)
     }
     /// Compares new hashmap to the last one and returns changed values
     /// # Examples
@@ -115,7 +655,7 @@ impl<K: Clone + Eq + Hash, V: Clone + PartialEq> HashMapComparer<K, V> {
     ///
     ///   let comparer = HashMapComparer::<u8, &str>::new();
     ///   let mut my_hashmap = HashMap::<u8, &str>::new();
-
+    ///
     ///   my_hashmap.insert(1, "foo");
     ///   assert_eq!(my_hashmap, comparer.compare(&my_hashmap).unwrap());
     ///
@@ -124,33 +664,576 @@ impl<K: Clone + Eq + Hash, V: Clone + PartialEq> HashMapComparer<K, V> {
     ///   my_hashmap.insert(2, "bar");
     ///   assert_eq!(HashMap::<u8, &str>::from_iter(vec![(2, "bar")]), comparer.compare(&my_hashmap).unwrap());
     ///
+    ///
+    /// ```
+    pub fn compare<M: MapLike<K, V>>(&self, new_map: &M) -> Result<HashMap<K, V>, ComparerError> {
+        let (last, seeded, settings) = self.current()?;
+        let new_map = &settings.key_filter.view(new_map);
+        Ok(self.changed_values(&last, seeded, settings.first_run, new_map))
+    }
+
+    /// Compares new hashmap to the last one and returns every added, removed and modified key.
+    /// Unlike `compare()` it also reports keys that no longer exist in the new hashmap.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{Change, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   let mut my_hashmap = HashMap::<u8, &str>::from_iter(vec![(1, "foo"), (2, "bar")]);
+    ///   comparer.update(&my_hashmap);
+    ///
+    ///   my_hashmap.remove(&1);
+    ///   let changes = comparer.diff(&my_hashmap);
+    ///   assert_eq!(vec![(&1, &Change::Removed("foo"))], changes.iter().collect::<Vec<_>>());
+    /// ```
+    pub fn diff<M: MapLike<K, V>>(&self, new_map: &M) -> ChangeSet<K, V> {
+        or_panic(self.try_diff(new_map))
+    }
+
+    /// Same as `diff()`, but returns an error instead of panicking
+    pub fn try_diff<M: MapLike<K, V>>(
+        &self,
+        new_map: &M,
+    ) -> Result<ChangeSet<K, V>, ComparerError> {
+        let (last, seeded, settings) = self.current()?;
+        let new_map = &settings.key_filter.view(new_map);
+        Ok(self.changes(&last, seeded, settings.first_run, new_map))
+    }
 
+    /// Updates last hashmap and returns every added, removed and modified key.
+    /// Same as `diff()` followed by `update()`, but no other update can happen in between.
+    /// # Examples
     /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{Change, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   let mut my_hashmap = HashMap::<u8, &str>::from_iter(vec![(1, "foo")]);
+    ///   assert_eq!(Some(&Change::Added("foo")), comparer.update_and_diff(&my_hashmap).get(&1));
+    ///
+    ///   my_hashmap.insert(1, "bar");
+    ///   assert_eq!(
+    ///       Some(&Change::Modified { old: "foo", new: "bar" }),
+    ///       comparer.update_and_diff(&my_hashmap).get(&1)
+    ///   );
+    ///
+    ///   my_hashmap.clear();
+    ///   assert_eq!(Some(&Change::Removed("bar")), comparer.update_and_diff(&my_hashmap).get(&1));
+    ///   assert!(comparer.update_and_diff(&my_hashmap).is_empty());
+    /// ```
+    pub fn update_and_diff<M: MapLike<K, V>>(&self, new_map: &M) -> ChangeSet<K, V> {
+        or_panic(self.try_update_and_diff(new_map))
+    }
 
-    pub fn compare(
+    /// Same as `update_and_diff()`, but returns an error instead of panicking
+    pub fn try_update_and_diff<M: MapLike<K, V>>(
         &self,
-        new_map: &HashMap<K, V>,
-    ) -> Result<HashMap<K, V>, Box<dyn std::error::Error>> {
-        let last_map = self.last_map.lock().unwrap();
-        let mut changed_values: HashMap<K, V> = HashMap::new();
-        if !last_map.is_empty() {
-            for (key, value) in new_map.iter() {
-                if !last_map.contains_key(key) || value != last_map.get(key).unwrap() {
-                    changed_values.insert(key.clone(), value.clone());
+        new_map: &M,
+    ) -> Result<ChangeSet<K, V>, ComparerError> {
+        let writer = self.lock_writer()?;
+        let (last, seeded, settings) = self.current()?;
+        let new_map = &settings.key_filter.view(new_map);
+        settings.check_capacity(new_map.len())?;
+        let changes = self.changes(&last, seeded, settings.first_run, new_map);
+        drop(last);
+        self.swap(new_map)?;
+        let notified = self.subscribers.is_active().then(|| changes.clone());
+        self.updated(writer, notified);
+        Ok(changes)
+    }
+
+    /// Blocks until last hashmap differs from the one it had in generation `since`, or until `timeout` elapses.
+    /// Returns the current generation together with the changes made since `since`, or `None` on timeout.
+    /// Updates that don't change anything don't wake the caller up.
+    ///
+    /// Changes are computed against the hashmap of generation `since`, so when called it has to be
+    /// the current generation or kept in history (see `with_history()`). Otherwise the changes since then
+    /// are unknown and `ComparerError::GenerationNotRetained` is returned. Without history this happens
+    /// whenever an update lands between reading the generation and calling `wait_for_change()`,
+    /// so be ready to resync, e.g. from `to_snapshot()` which returns the hashmap together with its generation.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use std::thread;
+    ///   use std::time::Duration;
+    ///   use comparer::{Change, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new().with_history(8);
+    ///   let since = comparer.generation();
+    ///   assert!(comparer.wait_for_change(since, Duration::from_millis(10)).unwrap().is_none());
+    ///
+    ///   let producer = comparer.clone();
+    ///   let handle = thread::spawn(move || producer.update(&HashMap::from([(1, "foo")])));
+    ///   let (generation, changes) = comparer.wait_for_change(since, Duration::from_secs(10)).unwrap().unwrap();
+    ///   handle.join().unwrap();
+    ///   assert_eq!(1, generation);
+    ///   assert_eq!(Some(&Change::Added("foo")), changes.get(&1));
+    ///
+    ///   // Changes made by several updates are accumulated
+    ///   comparer.update(&HashMap::from([(1, "bar")]));
+    ///   comparer.update(&HashMap::from([(1, "bar"), (2, "baz")]));
+    ///   let (generation, changes) = comparer.wait_for_change(generation, Duration::ZERO).unwrap().unwrap();
+    ///   assert_eq!(3, generation);
+    ///   assert_eq!(Some(&Change::Modified { old: "foo", new: "bar" }), changes.get(&1));
+    ///   assert_eq!(Some(&Change::Added("baz")), changes.get(&2));
+    /// ```
+    /// Waiting since a generation that is no longer kept fails instead of guessing the changes:
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use std::time::Duration;
+    ///   use comparer::{ComparerError, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   comparer.update(&HashMap::from([(1, "foo"), (2, "bar")]));
+    ///   let since = comparer.generation();
+    ///   // Another thread updates before we start waiting
+    ///   comparer.update(&HashMap::from([(1, "foo")]));
+    ///   assert_eq!(
+    ///       Err(ComparerError::GenerationNotRetained(since)),
+    ///       comparer.wait_for_change(since, Duration::ZERO)
+    ///   );
+    ///
+    ///   // Resync and continue from the generation of the snapshot
+    ///   let snapshot = comparer.to_snapshot();
+    ///   assert_eq!(HashMap::from([(1, "foo")]), snapshot.map);
+    ///   assert_eq!(Ok(None), comparer.wait_for_change(snapshot.generation, Duration::ZERO));
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn wait_for_change(
+        &self,
+        since: u64,
+        timeout: Duration,
+    ) -> Result<Option<(u64, ChangeSet<K, V>)>, ComparerError> {
+        let deadline = Instant::now() + timeout;
+        let mut checked = since;
+        let mut baseline = self.lock()?;
+        // Holding on to the hashmap of `since` keeps it around even if history drops it
+        let Some(old) = baseline.snapshot(since).cloned() else {
+            return Err(ComparerError::GenerationNotRetained(since));
+        };
+        loop {
+            if baseline.generation > checked {
+                let generation = baseline.generation;
+                let current = baseline.map.clone();
+                drop(baseline);
+                let changes = change::diff_maps(old.as_ref(), current.as_ref(), &*self.eq);
+                if !changes.is_empty() {
+                    return Ok(Some((generation, changes)));
                 }
+                checked = generation;
+                // Another update may have happened while diffing, check it before waiting
+                baseline = self.lock()?;
+                continue;
             }
-            return Ok(changed_values);
+            let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+                return Ok(None);
+            };
+            baseline = self.baseline.wait(&self.changed, baseline, remaining)?;
         }
-        Ok(new_map.clone())
+    }
+
+    /// Registers a callback called with the changes every time `update()`, `update_and_compare()`
+    /// or `update_and_diff()` detects a difference. Callbacks are shared by every clone of the comparer.
+    ///
+    /// Callbacks run after the new hashmap was stored, without holding any lock, so they may use
+    /// the comparer themselves. They receive changes in the order of the updates: usually on the thread
+    /// that updated the comparer, but if another thread is still delivering earlier changes,
+    /// that thread delivers the later ones too and the updating thread returns right away.
+    /// A callback that panics is unsubscribed, the panic doesn't reach the updating thread
+    /// and the remaining callbacks are still called. `reset()` doesn't call any callback.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use std::sync::{Arc, Mutex};
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   let seen = Arc::new(Mutex::new(Vec::new()));
+    ///   let sink = seen.clone();
+    ///   let subscription = comparer.subscribe(move |changes| sink.lock().unwrap().push(changes.len()));
+    ///
+    ///   let mut my_hashmap = HashMap::<u8, &str>::from_iter(vec![(1, "foo"), (2, "bar")]);
+    ///   comparer.update(&my_hashmap);
+    ///   // Nothing changed, so callback isn't called
+    ///   comparer.update(&my_hashmap);
+    ///   my_hashmap.remove(&1);
+    ///   comparer.update(&my_hashmap);
+    ///
+    ///   assert!(comparer.unsubscribe(subscription));
+    ///   my_hashmap.remove(&2);
+    ///   comparer.update(&my_hashmap);
+    ///   assert_eq!(vec![2, 1], *seen.lock().unwrap());
+    /// ```
+    /// A panicking callback is removed without affecting the update:
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   let subscription = comparer.subscribe(|_| panic!("subscriber failed"));
+    ///   comparer.update(&HashMap::from([(1, "foo")]));
+    ///
+    ///   assert_eq!(HashMap::from([(1, "foo")]), comparer.clone_last());
+    ///   assert!(!comparer.unsubscribe(subscription));
+    /// ```
+    /// Changes arrive in order even with concurrent updates, so they can be replayed onto a replica:
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use std::sync::{Arc, Mutex};
+    ///   use std::thread;
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let comparer = HashMapComparer::<u32, u32>::new();
+    ///   let replica = Arc::new(Mutex::new(HashMap::new()));
+    ///   let sink = replica.clone();
+    ///   comparer.subscribe(move |changes| changes.apply_to(&mut sink.lock().unwrap()));
+    ///
+    ///   let handles: Vec<_> = (0..4u32)
+    ///       .map(|thread| {
+    ///           let comparer = comparer.clone();
+    ///           thread::spawn(move || {
+    ///               for i in 0..200u32 {
+    ///                   comparer.update(&HashMap::from([(i % 7, thread), (10 + thread, i)]));
+    ///               }
+    ///           })
+    ///       })
+    ///       .collect();
+    ///   for handle in handles {
+    ///       handle.join().unwrap();
+    ///   }
+    ///   assert_eq!(comparer.clone_last(), *replica.lock().unwrap());
+    /// ```
+    pub fn subscribe(
+        &self,
+        callback: impl Fn(&ChangeSet<K, V>) + Send + Sync + 'static,
+    ) -> Subscription {
+        self.subscribers.subscribe_all(Arc::new(callback))
+    }
+
+    /// Registers a callback called only when the given key changes, with changes of that key only.
+    /// See `subscribe()` for details.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use std::sync::{Arc, Mutex};
+    ///   use comparer::{Change, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   let seen = Arc::new(Mutex::new(Vec::new()));
+    ///   let sink = seen.clone();
+    ///   comparer.subscribe_key(1, move |changes| {
+    ///       sink.lock().unwrap().extend(changes.iter().map(|(_, change)| change.clone()))
+    ///   });
+    ///
+    ///   comparer.update(&HashMap::from([(1, "foo"), (2, "bar")]));
+    ///   comparer.update(&HashMap::from([(1, "foo"), (2, "baz")]));
+    ///   comparer.update(&HashMap::from([(2, "baz")]));
+    ///   assert_eq!(vec![Change::Added("foo"), Change::Removed("foo")], *seen.lock().unwrap());
+    /// ```
+    pub fn subscribe_key(
+        &self,
+        key: K,
+        callback: impl Fn(&ChangeSet<K, V>) + Send + Sync + 'static,
+    ) -> Subscription {
+        self.subscribers.subscribe_key(key, Arc::new(callback))
+    }
+
+    /// Registers a callback called only when keys matching the predicate change, with changes of those keys only.
+    /// See `subscribe()` for details.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use std::sync::{Arc, Mutex};
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   let seen = Arc::new(Mutex::new(Vec::new()));
+    ///   let sink = seen.clone();
+    ///   comparer.subscribe_matching(
+    ///       |key| key % 2 == 0,
+    ///       move |changes| sink.lock().unwrap().extend(changes.iter().map(|(key, _)| *key)),
+    ///   );
+    ///
+    ///   comparer.update(&HashMap::from([(1, "foo"), (2, "bar")]));
+    ///   comparer.update(&HashMap::from([(1, "bar"), (2, "bar")]));
+    ///   assert_eq!(vec![2], *seen.lock().unwrap());
+    /// ```
+    pub fn subscribe_matching(
+        &self,
+        predicate: impl Fn(&K) -> bool + Send + Sync + 'static,
+        callback: impl Fn(&ChangeSet<K, V>) + Send + Sync + 'static,
+    ) -> Subscription {
+        self.subscribers
+            .subscribe_matching(Arc::new(predicate), Arc::new(callback))
+    }
+
+    /// Removes a callback, returns false if it was already removed
+    pub fn unsubscribe(&self, subscription: Subscription) -> bool {
+        self.subscribers.unsubscribe(subscription)
+    }
+
+    /// Registers a named consumer with its own cursor, returns false if the name is already taken.
+    ///
+    /// Producer publishes new hashmaps once with `update()` (or any other updating method) and every
+    /// consumer reads the net changes since its previous read with `consume()`, so consumers sharing
+    /// a comparer don't steal changes from each other. A new consumer starts from an empty hashmap,
+    /// its first read reports every key as added. Consumers are shared by every clone of the comparer.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{Change, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   assert!(comparer.add_consumer("cache"));
+    ///   assert!(comparer.add_consumer("search"));
+    ///
+    ///   comparer.update(&HashMap::from([(1, "foo")]));
+    ///   assert_eq!(Some(&Change::Added("foo")), comparer.consume("cache").unwrap().get(&1));
+    ///
+    ///   comparer.update(&HashMap::from([(1, "bar")]));
+    ///   comparer.update(&HashMap::from([(1, "baz")]));
+    ///   let changes = comparer.consume("cache").unwrap();
+    ///   assert_eq!(Some(&Change::Modified { old: "foo", new: "baz" }), changes.get(&1));
+    ///   // Search didn't read yet, so it still gets everything
+    ///   assert_eq!(Some(&Change::Added("baz")), comparer.consume("search").unwrap().get(&1));
+    ///   assert!(comparer.consume("search").unwrap().is_empty());
+    ///
+    ///   assert!(comparer.remove_consumer("search"));
+    ///   assert!(comparer.consume("search").is_none());
+    ///   assert_eq!(vec!["cache".to_string()], comparer.consumers());
+    /// ```
+    pub fn add_consumer(&self, name: impl Into<String>) -> bool {
+        or_panic(self.try_add_consumer(name))
+    }
+
+    /// Same as `add_consumer()`, but returns an error instead of panicking
+    pub fn try_add_consumer(&self, name: impl Into<String>) -> Result<bool, ComparerError> {
+        let mut baseline = self.lock()?;
+        Ok(match baseline.consumers.entry(name.into()) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(Arc::new(HashMap::new()));
+                true
+            }
+        })
+    }
+
+    /// Removes a consumer, returns false if there is no consumer with that name
+    pub fn remove_consumer(&self, name: &str) -> bool {
+        or_panic(self.try_remove_consumer(name))
+    }
+
+    /// Same as `remove_consumer()`, but returns an error instead of panicking
+    pub fn try_remove_consumer(&self, name: &str) -> Result<bool, ComparerError> {
+        Ok(self.lock()?.consumers.remove(name).is_some())
+    }
+
+    /// Names of every registered consumer, in no particular order
+    pub fn consumers(&self) -> Vec<String> {
+        or_panic(self.try_consumers())
+    }
+
+    /// Same as `consumers()`, but returns an error instead of panicking
+    pub fn try_consumers(&self) -> Result<Vec<String>, ComparerError> {
+        Ok(self.lock()?.consumers.keys().cloned().collect())
+    }
+
+    /// Returns net changes of last hashmap since the consumer read them the previous time
+    /// and moves its cursor to the current hashmap. Returns `None` if there is no consumer with that name.
+    /// Changes are computed without holding the lock, and retried if the same consumer read concurrently.
+    pub fn consume(&self, name: &str) -> Option<ChangeSet<K, V>> {
+        or_panic(self.try_consume(name))
+    }
+
+    /// Same as `consume()`, but returns an error instead of panicking
+    pub fn try_consume(&self, name: &str) -> Result<Option<ChangeSet<K, V>>, ComparerError> {
+        loop {
+            let baseline = self.lock()?;
+            let Some(seen) = baseline.consumers.get(name).cloned() else {
+                return Ok(None);
+            };
+            let current = baseline.map.clone();
+            drop(baseline);
+            let changes = change::diff_maps(seen.as_ref(), current.as_ref(), &*self.eq);
+            let mut baseline = self.lock()?;
+            match baseline.consumers.get_mut(name) {
+                None => return Ok(None),
+                Some(cursor) if Arc::ptr_eq(cursor, &seen) => {
+                    *cursor = current;
+                    return Ok(Some(changes));
+                }
+                Some(_) => continue,
+            }
+        }
+    }
+
+    /// Applies changes to last hashmap without comparing whole hashmaps and returns the applied changes.
+    /// Counts as an update: it starts a new generation, wakes up `wait_for_change()` and calls subscribers.
+    /// Changes of keys ignored by the key filter are dropped.
+    /// Last hashmap has to be set first, otherwise `try_apply()` returns `ComparerError::NotSeeded`.
+    ///
+    /// Changes are applied in place in O(changes) time only if nothing else refers to last hashmap.
+    /// Otherwise it is copied first, which takes O(keys) time, while holding the lock. That happens when:
+    /// - history is kept, see `with_history()`,
+    /// - a snapshot returned by `snapshot()` is still alive, or a fork still shares it, see `fork()`,
+    /// - a consumer read it last with `consume()`,
+    /// - a `wait_for_change()` is waiting since its generation,
+    /// - a reader like `is_same()` or `diff()` is comparing against it at the same time.
+    ///
+    /// Old values in the changes are not checked against last hashmap. See `TrackedMap` for a way
+    /// to collect changes as they are made.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{Change, ChangeSet, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new();
+    ///   comparer.update(&HashMap::from([(1, "foo"), (2, "bar")]));
+    ///
+    ///   let changes = ChangeSet::from_iter(vec![(1, Change::Removed("foo")), (3, Change::Added("baz"))]);
+    ///   comparer.apply(&changes);
+    ///   assert_eq!(2, comparer.generation());
+    ///   assert_eq!(HashMap::from([(2, "bar"), (3, "baz")]), comparer.clone_last());
+    /// ```
+    pub fn apply(&self, changes: &ChangeSet<K, V>) -> ChangeSet<K, V> {
+        or_panic(self.try_apply(changes))
+    }
+
+    /// Same as `apply()`, but returns an error instead of panicking
+    pub fn try_apply(&self, changes: &ChangeSet<K, V>) -> Result<ChangeSet<K, V>, ComparerError> {
+        let writer = self.lock_writer()?;
+        let (_, seeded, settings) = self.current()?;
+        if !seeded {
+            return Err(ComparerError::NotSeeded);
+        }
+        let changes: ChangeSet<K, V> = changes
+            .iter()
+            .filter(|(key, _)| settings.key_filter.allows(key))
+            .map(|(key, change)| (key.clone(), change.clone()))
+            .collect();
+        let mut baseline = self.lock()?;
+        let len = changes
+            .iter()
+            .fold(baseline.map.len(), |len, (key, change)| {
+                match (baseline.map.contains_key(key), change.new_value()) {
+                    (false, Some(_)) => len + 1,
+                    (true, None) => len - 1,
+                    _ => len,
+                }
+            });
+        settings.check_capacity(len)?;
+        baseline.apply(&changes);
+        drop(baseline);
+        let notified = self.subscribers.is_active().then(|| changes.clone());
+        self.updated(writer, notified);
+        Ok(changes)
+    }
+
+    /// Equality function used to compare values
+    pub(crate) fn eq_fn(&self) -> &(dyn Fn(&V, &V) -> bool + Send + Sync) {
+        &*self.eq
+    }
+
+    /// Locks last hashmap. The lock is only held to read or swap the `Arc`s, never while comparing.
+    fn lock(&self) -> Result<MutexGuard<'_, Baseline<K, V>>, ComparerError> {
+        self.baseline.lock()
+    }
+
+    fn lock_writer(&self) -> Result<MutexGuard<'_, ()>, ComparerError> {
+        self.baseline.lock_writer()
+    }
+
+    /// Snapshot of last hashmap, whether it was ever set and the settings it was stored with
+    #[allow(clippy::type_complexity)]
+    fn current(&self) -> Result<(Arc<HashMap<K, V>>, bool, Settings<K>), ComparerError> {
+        let baseline = self.lock()?;
+        Ok((
+            baseline.map.clone(),
+            baseline.seeded,
+            baseline.settings.clone(),
+        ))
+    }
+
+    /// Copies `new_map` without holding the lock and swaps it in as last hashmap
+    fn swap(&self, new_map: &impl MapLike<K, V>) -> Result<(), ComparerError> {
+        let map = new_map
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        let dropped = self.lock()?.replace(map, true);
+        drop(dropped);
+        Ok(())
+    }
+
+    /// Ends an update: queues the changes for subscribers while other updates are still locked out,
+    /// releases the writer lock, wakes up threads waiting for a change and delivers the queued changes
+    fn updated(&self, writer: MutexGuard<'_, ()>, changes: Option<ChangeSet<K, V>>) {
+        if let Some(changes) = changes {
+            self.subscribers.enqueue(changes);
+        }
+        drop(writer);
+        self.changed.notify_all();
+        self.subscribers.deliver();
+    }
+
+    /// Collects added and modified values of `new_map`, following the first run policy if baseline wasn't set yet
+    fn changed_values(
+        &self,
+        last: &HashMap<K, V>,
+        seeded: bool,
+        first_run: FirstRun,
+        new_map: &impl MapLike<K, V>,
+    ) -> HashMap<K, V> {
+        change::changed_values(last, seeded, first_run, new_map, &*self.eq)
+    }
+
+    /// Diffs `new_map` against the baseline, following the first run policy if baseline wasn't set yet
+    fn changes<M: MapLike<K, V>>(
+        &self,
+        last: &HashMap<K, V>,
+        seeded: bool,
+        first_run: FirstRun,
+        new_map: &M,
+    ) -> ChangeSet<K, V> {
+        change::diff_last(last, seeded, first_run, new_map, &*self.eq)
     }
 }
 
 /// HashMapComparer
-/// struct that contains `last_map` and impliments several methods for it
-#[derive(Debug, Clone)]
-pub struct HashMapComparer<K: Clone + Eq + Hash, V: Clone + PartialEq> {
-    last_map: Arc<Mutex<HashMap<K, V>>>,
+/// struct that contains last hashmap and impliments several methods for it.
+/// New maps can be any `MapLike`, e.g. a `HashMap` with a custom hasher or a `BTreeMap`
+///
+/// Last hashmap is kept as an immutable `Arc` snapshot. Readers like `is_same()`, `compare()` and `diff()`
+/// only lock to clone the `Arc` and compare without blocking each other or updates.
+/// Updates build the new hashmap aside and swap it in, so readers always see either the old or the new one.
+/// Updates are serialized among themselves.
+///
+/// `clone()` returns another handle to the same comparer, like `share()`. Use `fork()` for an independent copy.
+/// Handles share the settings too: changing them with a `with_` method through one handle changes them for all.
+#[derive(Clone)]
+pub struct HashMapComparer<K: Clone + Eq + Hash, V: Clone> {
+    baseline: Shared<Baseline<K, V>>,
+    changed: Arc<Condvar>,
+    subscribers: Arc<Subscribers<K, V>>,
+    eq: Equality<V>,
 }
+
+impl<K: Clone + Eq + Hash + fmt::Debug, V: Clone + fmt::Debug> fmt::Debug
+    for HashMapComparer<K, V>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashMapComparer")
+            .field("baseline", &self.baseline)
+            .field("subscribers", &self.subscribers)
+            .finish_non_exhaustive()
+    }
+}
+/// Unwraps result of a `try_` method for its panicking variant
+fn or_panic<T>(result: Result<T, ComparerError>) -> T {
+    result.unwrap_or_else(|error| panic!("{error}"))
+}
+
 impl<K: Clone + Eq + Hash, V: Clone + PartialEq> Default for HashMapComparer<K, V> {
     fn default() -> Self {
         HashMapComparer::new()
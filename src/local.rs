@@ -0,0 +1,190 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::{change, or_panic, ChangeSet, Equality, FirstRun, HashMapComparer, KeyFilter, MapLike};
+
+/// Comparer for use from a single thread or task, without any locking
+///
+/// Has the same comparisons as `HashMapComparer`, but owns last hashmap directly and updates it through `&mut self`,
+/// so nothing is shared and nothing can fail. Cloning it copies last hashmap, the clone is independent.
+/// Convert it with `into_shared()` when it has to be handed to other threads.
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::{Change, LocalComparer};
+///
+///   let mut comparer = LocalComparer::<u8, &str>::new();
+///   let mut my_hashmap = HashMap::from([(1, "foo")]);
+///   assert_eq!(my_hashmap, comparer.update_and_compare(&my_hashmap));
+///
+///   let mut branch = comparer.clone();
+///   my_hashmap.insert(1, "bar");
+///   assert_eq!(Some(&Change::Modified { old: "foo", new: "bar" }), branch.update_and_diff(&my_hashmap).get(&1));
+///   // Clone has its own last hashmap
+///   assert_eq!(Some(&"foo"), comparer.last().get(&1));
+///
+///   let shared = comparer.into_shared();
+///   assert_eq!(HashMap::from([(1, "bar")]), shared.compare(&my_hashmap).unwrap());
+/// ```
+/// It can be moved to another thread, but not shared between threads:
+/// ```compile_fail
+///   fn shareable<T: Sync>(_: &T) {}
+///   shareable(&comparer::LocalComparer::<u8, u8>::new());
+/// ```
+#[derive(Clone)]
+pub struct LocalComparer<K, V> {
+    last: HashMap<K, V>,
+    seeded: bool,
+    first_run: FirstRun,
+    key_filter: KeyFilter<K>,
+    eq: Equality<V>,
+    /// Keeps it `Send` but not `Sync`
+    not_sync: PhantomData<Cell<()>>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + PartialEq> LocalComparer<K, V> {
+    pub fn new() -> Self {
+        Self::with_eq(|a: &V, b: &V| a == b)
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> LocalComparer<K, V> {
+    /// Creates comparer that uses a custom function instead of `==`, see `HashMapComparer::with_eq()`.
+    /// The function has to be `Send + Sync` so the comparer can still be converted with `into_shared()`.
+    pub fn with_eq(eq: impl Fn(&V, &V) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            last: HashMap::new(),
+            seeded: false,
+            first_run: FirstRun::default(),
+            key_filter: KeyFilter::all(),
+            eq: Arc::new(eq),
+            not_sync: PhantomData,
+        }
+    }
+
+    /// Sets what is reported by the first comparison, see `HashMapComparer::with_first_run()`
+    pub fn with_first_run(mut self, first_run: FirstRun) -> Self {
+        self.first_run = first_run;
+        self
+    }
+
+    /// Makes comparer watch only keys allowed by the filter, see `HashMapComparer::with_key_filter()`
+    pub fn with_key_filter(mut self, key_filter: KeyFilter<K>) -> Self {
+        self.key_filter = key_filter;
+        self
+    }
+
+    /// Last hashmap
+    pub fn last(&self) -> &HashMap<K, V> {
+        &self.last
+    }
+
+    /// Clones last hashmap
+    pub fn clone_last(&self) -> HashMap<K, V> {
+        self.last.clone()
+    }
+
+    /// Returns true if last hashmap was set at least once since the comparer was created or reset
+    pub fn is_seeded(&self) -> bool {
+        self.seeded
+    }
+
+    /// Clears last hashmap, next comparison is treated as the first one again
+    pub fn reset(&mut self) {
+        self.last.clear();
+        self.seeded = false;
+    }
+
+    /// Checks if last hashmap is the same as new one, see `HashMapComparer::is_same()`
+    pub fn is_same<M: MapLike<K, V>>(&self, comparable: &M) -> bool {
+        let comparable = &self.key_filter.view(comparable);
+        change::same_maps(&self.last, comparable, &*self.eq)
+    }
+
+    /// Updates last hashmap to a new value
+    pub fn update<M: MapLike<K, V>>(&mut self, new_map: &M) {
+        let Self {
+            last, key_filter, ..
+        } = self;
+        let new_map = &key_filter.view(new_map);
+        last.clear();
+        last.extend(
+            new_map
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        );
+        self.seeded = true;
+    }
+
+    /// Checks if last hashmap is the same as new one and updates it to be that new value
+    pub fn is_same_update<M: MapLike<K, V>>(&mut self, new_map: &M) -> bool {
+        let is_same = self.is_same(new_map);
+        self.update(new_map);
+        is_same
+    }
+
+    /// Compares new hashmap to the last one and returns changed values
+    pub fn compare<M: MapLike<K, V>>(&self, new_map: &M) -> HashMap<K, V> {
+        let new_map = &self.key_filter.view(new_map);
+        change::changed_values(&self.last, self.seeded, self.first_run, new_map, &*self.eq)
+    }
+
+    /// Updates last hashmap, compares new one to the last one and returns changed values
+    pub fn update_and_compare<M: MapLike<K, V>>(&mut self, new_map: &M) -> HashMap<K, V> {
+        let changed_values = self.compare(new_map);
+        self.update(new_map);
+        changed_values
+    }
+
+    /// Compares new hashmap to the last one and returns every added, removed and modified key
+    pub fn diff<M: MapLike<K, V>>(&self, new_map: &M) -> ChangeSet<K, V> {
+        let new_map = &self.key_filter.view(new_map);
+        change::diff_last(&self.last, self.seeded, self.first_run, new_map, &*self.eq)
+    }
+
+    /// Updates last hashmap and returns every added, removed and modified key
+    pub fn update_and_diff<M: MapLike<K, V>>(&mut self, new_map: &M) -> ChangeSet<K, V> {
+        let changes = self.diff(new_map);
+        self.update(new_map);
+        changes
+    }
+
+    /// Converts into a comparer that can be shared between threads, keeping last hashmap and settings
+    pub fn into_shared(self) -> HashMapComparer<K, V> {
+        let shared = HashMapComparer::from_eq(self.eq)
+            .with_first_run(self.first_run)
+            .with_key_filter(self.key_filter);
+        let mut baseline = or_panic(shared.lock());
+        baseline.map = Arc::new(self.last);
+        baseline.seeded = self.seeded;
+        drop(baseline);
+        shared
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> From<LocalComparer<K, V>> for HashMapComparer<K, V> {
+    fn from(local: LocalComparer<K, V>) -> Self {
+        local.into_shared()
+    }
+}
+
+impl<K: fmt::Debug, V: fmt::Debug> fmt::Debug for LocalComparer<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalComparer")
+            .field("last", &self.last)
+            .field("seeded", &self.seeded)
+            .field("first_run", &self.first_run)
+            .field("key_filter", &self.key_filter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + PartialEq> Default for LocalComparer<K, V> {
+    fn default() -> Self {
+        LocalComparer::new()
+    }
+}
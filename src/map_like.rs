@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+use crate::ChangeSet;
+
+/// Read access to a map that can be compared by `HashMapComparer`
+///
+/// Implemented for `HashMap` with any hasher and for `BTreeMap`, so they can be
+/// compared without converting them into a `HashMap` with the default hasher first.
+/// # Examples
+/// ```
+///   use std::collections::{BTreeMap, HashMap};
+///   use std::collections::hash_map::DefaultHasher;
+///   use std::hash::BuildHasherDefault;
+///   use comparer::{Change, HashMapComparer};
+///
+///   let comparer = HashMapComparer::<u8, &str>::new();
+///   comparer.update(&BTreeMap::from_iter(vec![(1, "foo"), (3, "bar")]));
+///
+///   let mut custom_hasher = HashMap::<u8, &str, BuildHasherDefault<DefaultHasher>>::default();
+///   custom_hasher.insert(1, "foo");
+///   custom_hasher.insert(3, "bar");
+///   assert!(comparer.is_same(&custom_hasher));
+///
+///   // Changes of a BTreeMap come back in key order
+///   let changes = comparer.diff(&BTreeMap::from_iter(vec![(0, "foo"), (1, "baz"), (2, "foo")]));
+///   assert_eq!(
+///       vec![
+///           (&0, &Change::Added("foo")),
+///           (&1, &Change::Modified { old: "foo", new: "baz" }),
+///           (&2, &Change::Added("foo")),
+///           (&3, &Change::Removed("bar")),
+///       ],
+///       changes.iter().collect::<Vec<_>>()
+///   );
+/// ```
+pub trait MapLike<K, V> {
+    /// Returns value of a key
+    fn get(&self, key: &K) -> Option<&V>;
+
+    /// Iterates over every key and value of the map
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a;
+
+    /// Number of keys in the map
+    fn len(&self) -> usize;
+
+    /// Returns true if the map has no keys
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if the map has a value for the key
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Called with changes detected against this map, so maps with a defined key order
+    /// can put the changes in that order. Does nothing by default.
+    fn order_changes(_changes: &mut ChangeSet<K, V>) {}
+}
+
+impl<K: Eq + Hash, V, S: BuildHasher> MapLike<K, V> for HashMap<K, V, S> {
+    fn get(&self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.iter()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+}
+
+impl<K: Ord, V> MapLike<K, V> for BTreeMap<K, V> {
+    fn get(&self, key: &K) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a K, &'a V)>
+    where
+        K: 'a,
+        V: 'a,
+    {
+        self.iter()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.contains_key(key)
+    }
+
+    fn order_changes(changes: &mut ChangeSet<K, V>) {
+        changes.sort();
+    }
+}
@@ -0,0 +1,440 @@
+//! Encoding of keys and values for `HashMapComparer::save()` and `HashMapComparer::load()`
+//!
+//! A saved baseline looks like this, every integer is little endian:
+//!
+//! | Field          | Size                                                         |
+//! |----------------|--------------------------------------------------------------|
+//! | magic `CMPR`   | 4 bytes                                                      |
+//! | format version | u16                                                          |
+//! | flags          | u8, bit 0 is set if the baseline was seeded                  |
+//! | generation     | u64                                                          |
+//! | entry count    | u64                                                          |
+//! | entries        | u32 length and bytes of the key, then the same for the value |
+//! | checksum       | u64, FNV-1a of everything before it                          |
+//!
+//! Keys and values are encoded with `Encode` and decoded with `Decode`, which are implemented
+//! for integers, floats, `bool`, `char`, `String`, `Vec`, `Option` and tuples.
+//! # Examples
+//! ```
+//!   use comparer::persist::{Decode, Encode};
+//!   use comparer::ComparerError;
+//!
+//!   // Stores a point as two bytes
+//!   #[derive(Debug, PartialEq)]
+//!   struct Point(u8, u8);
+//!
+//!   impl Encode for Point {
+//!       fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+//!           out.extend([self.0, self.1]);
+//!           Ok(())
+//!       }
+//!   }
+//!
+//!   impl Decode for Point {
+//!       fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+//!           Ok(Point(u8::decode(bytes)?, u8::decode(bytes)?))
+//!       }
+//!   }
+//!
+//!   let mut out = Vec::new();
+//!   Point(3, 4).encode(&mut out).unwrap();
+//!   assert_eq!(Ok(Point(3, 4)), Point::decode(&mut out.as_slice()));
+//! ```
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::Hash;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+
+use crate::{ComparerError, HashMapComparer, Snapshot};
+
+const MAGIC: &[u8; 4] = b"CMPR";
+const VERSION: u16 = 1;
+const SEEDED: u8 = 1;
+
+/// Writes a value as bytes, fails with `ComparerError::TooLong` if a length doesn't fit into the format
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError>;
+}
+
+/// Reads a value written by `Encode`, advancing `bytes` past it
+pub trait Decode: Sized {
+    fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError>;
+}
+
+/// Splits `len` bytes off the front of `bytes`
+pub fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], ComparerError> {
+    if bytes.len() < len {
+        return Err(ComparerError::Corrupt("unexpected end of data".to_string()));
+    }
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+macro_rules! encode_number {
+    ($($number:ty),*) => {
+        $(
+            impl Encode for $number {
+                fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+                    out.extend_from_slice(&self.to_le_bytes());
+                    Ok(())
+                }
+            }
+
+            impl Decode for $number {
+                fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+                    let taken = take(bytes, std::mem::size_of::<$number>())?;
+                    Ok(<$number>::from_le_bytes(taken.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+encode_number!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+/// Encoded as u64, so the data can be read on platforms with a different pointer size
+impl Encode for usize {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+        (*self as u64).encode(out)
+    }
+}
+
+impl Decode for usize {
+    fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+        usize::try_from(u64::decode(bytes)?)
+            .map_err(|_| ComparerError::Corrupt("usize out of range".to_string()))
+    }
+}
+
+/// Encoded as i64, so the data can be read on platforms with a different pointer size
+impl Encode for isize {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+        (*self as i64).encode(out)
+    }
+}
+
+impl Decode for isize {
+    fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+        isize::try_from(i64::decode(bytes)?)
+            .map_err(|_| ComparerError::Corrupt("isize out of range".to_string()))
+    }
+}
+
+impl Encode for bool {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+        out.push(*self as u8);
+        Ok(())
+    }
+}
+
+impl Decode for bool {
+    fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+        match u8::decode(bytes)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(ComparerError::Corrupt("invalid bool".to_string())),
+        }
+    }
+}
+
+impl Encode for char {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+        (*self as u32).encode(out)
+    }
+}
+
+impl Decode for char {
+    fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+        char::from_u32(u32::decode(bytes)?)
+            .ok_or_else(|| ComparerError::Corrupt("invalid char".to_string()))
+    }
+}
+
+/// Encoded as u32 length followed by UTF-8 bytes
+impl Encode for str {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+        encode_len(self.len(), out)?;
+        out.extend_from_slice(self.as_bytes());
+        Ok(())
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+        self.as_str().encode(out)
+    }
+}
+
+impl Decode for String {
+    fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+        let len = decode_len(bytes)?;
+        String::from_utf8(take(bytes, len)?.to_vec())
+            .map_err(|_| ComparerError::Corrupt("invalid UTF-8".to_string()))
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+        (**self).encode(out)
+    }
+}
+
+/// Encoded as u32 element count followed by the elements
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+        encode_len(self.len(), out)?;
+        for item in self {
+            item.encode(out)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+        let len = decode_len(bytes)?;
+        // Every element takes at least a byte in practice, don't trust a corrupt length with the allocation
+        let mut items = Vec::with_capacity(len.min(bytes.len()));
+        for _ in 0..len {
+            items.push(T::decode(bytes)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+        match self {
+            None => {
+                out.push(0);
+                Ok(())
+            }
+            Some(value) => {
+                out.push(1);
+                value.encode(out)
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+        match bool::decode(bytes)? {
+            false => Ok(None),
+            true => Ok(Some(T::decode(bytes)?)),
+        }
+    }
+}
+
+macro_rules! encode_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: Encode),*> Encode for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn encode(&self, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+                let ($($name,)*) = self;
+                $($name.encode(out)?;)*
+                Ok(())
+            }
+        }
+
+        impl<$($name: Decode),*> Decode for ($($name,)*) {
+            fn decode(bytes: &mut &[u8]) -> Result<Self, ComparerError> {
+                Ok(($($name::decode(bytes)?,)*))
+            }
+        }
+    };
+}
+
+encode_tuple!(A);
+encode_tuple!(A, B);
+encode_tuple!(A, B, C);
+encode_tuple!(A, B, C, D);
+
+fn encode_len(len: usize, out: &mut Vec<u8>) -> Result<(), ComparerError> {
+    u32::try_from(len)
+        .map_err(|_| ComparerError::TooLong(len))?
+        .encode(out)
+}
+
+fn decode_len(bytes: &mut &[u8]) -> Result<usize, ComparerError> {
+    Ok(u32::decode(bytes)? as usize)
+}
+
+impl<K: Clone + Eq + Hash + Encode, V: Clone + Encode> HashMapComparer<K, V> {
+    /// Saves last hashmap and its generation to a file, see `persist` module for the format.
+    /// The file is written next to the target first and renamed over it, so a crash never leaves a half written file.
+    /// If saving fails, the temporary file is removed and the target is left as it was.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::HashMapComparer;
+    ///
+    ///   let path = std::env::temp_dir().join(format!("comparer-doctest-{}.bin", std::process::id()));
+    ///   let comparer = HashMapComparer::<String, u32>::new();
+    ///   comparer.update(&HashMap::from([("workers".to_string(), 4)]));
+    ///   comparer.save(&path).unwrap();
+    ///
+    ///   // After a restart
+    ///   let restored = HashMapComparer::<String, u32>::new();
+    ///   restored.load(&path).unwrap();
+    ///   assert_eq!(1, restored.generation());
+    ///   assert!(restored.update_and_compare(&HashMap::from([("workers".to_string(), 4)])).unwrap().is_empty());
+    ///   std::fs::remove_file(&path).unwrap();
+    ///
+    ///   // A directory is in the way, so the file can't be renamed over it
+    ///   std::fs::create_dir(&path).unwrap();
+    ///   assert!(comparer.save(&path).is_err());
+    ///   assert!(!path.with_extension("bin.tmp").exists());
+    ///   std::fs::remove_dir(&path).unwrap();
+    /// ```
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ComparerError> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        let saved = File::create(&temporary)
+            .map_err(ComparerError::from)
+            .and_then(|mut file| {
+                self.save_to(&mut file)?;
+                file.sync_all()?;
+                fs::rename(&temporary, path)?;
+                Ok(())
+            });
+        if saved.is_err() {
+            // Don't leave a half written file behind, the original is still in place
+            let _ = fs::remove_file(&temporary);
+        }
+        saved
+    }
+
+    /// Writes last hashmap and its generation, see `save()`
+    pub fn save_to(&self, mut writer: impl Write) -> Result<(), ComparerError> {
+        let (map, seeded, generation) = {
+            let baseline = self.lock()?;
+            (baseline.map.clone(), baseline.seeded, baseline.generation)
+        };
+        write(&mut writer, map.iter(), seeded, generation)
+    }
+}
+
+impl<K: Clone + Eq + Hash + Decode, V: Clone + Decode> HashMapComparer<K, V> {
+    /// Replaces last hashmap with one saved by `save()`, continuing from its generation.
+    /// Works like `restore()` with the saved snapshot.
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<(), ComparerError> {
+        self.load_from(BufReader::new(File::open(path)?))
+    }
+
+    /// Reads last hashmap written by `save_to()`, see `load()`
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{ComparerError, HashMapComparer};
+    ///
+    ///   let comparer = HashMapComparer::<u8, String>::new();
+    ///   comparer.update(&HashMap::from([(1, "foo".to_string())]));
+    ///   let mut saved = Vec::new();
+    ///   comparer.save_to(&mut saved).unwrap();
+    ///
+    ///   let restored = HashMapComparer::<u8, String>::new();
+    ///   assert!(matches!(restored.load_from(&saved[..saved.len() - 1]), Err(ComparerError::Corrupt(_))));
+    ///   saved[20] ^= 1;
+    ///   assert!(matches!(restored.load_from(saved.as_slice()), Err(ComparerError::Corrupt(_))));
+    ///   saved[20] ^= 1;
+    ///   restored.load_from(saved.as_slice()).unwrap();
+    ///   assert_eq!(comparer.clone_last(), restored.clone_last());
+    /// ```
+    pub fn load_from(&self, mut reader: impl Read) -> Result<(), ComparerError> {
+        self.restore(read(&mut reader)?)
+    }
+}
+
+pub(crate) fn write<'a, K: Encode + 'a, V: Encode + 'a>(
+    writer: &mut impl Write,
+    entries: impl ExactSizeIterator<Item = (&'a K, &'a V)>,
+    seeded: bool,
+    generation: u64,
+) -> Result<(), ComparerError> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    VERSION.encode(&mut out)?;
+    out.push(if seeded { SEEDED } else { 0 });
+    generation.encode(&mut out)?;
+    (entries.len() as u64).encode(&mut out)?;
+    let mut encoded = Vec::new();
+    for (key, value) in entries {
+        for item in [key as &dyn Encode, value as &dyn Encode] {
+            encoded.clear();
+            item.encode(&mut encoded)?;
+            encode_len(encoded.len(), &mut out)?;
+            out.extend_from_slice(&encoded);
+        }
+    }
+    fnv1a(&out).encode(&mut out)?;
+    writer.write_all(&out)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub(crate) fn read<K: Decode + Eq + Hash, V: Decode>(
+    reader: &mut impl Read,
+) -> Result<Snapshot<K, V>, ComparerError> {
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let bytes = &mut data.as_slice();
+    if take(bytes, MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(ComparerError::Corrupt(
+            "not a saved comparer baseline".to_string(),
+        ));
+    }
+    let version = u16::decode(bytes)?;
+    if version != VERSION {
+        return Err(ComparerError::UnsupportedVersion(version));
+    }
+    let Some(checksummed) = data.len().checked_sub(8) else {
+        return Err(ComparerError::Corrupt("unexpected end of data".to_string()));
+    };
+    let checksum = u64::decode(&mut &data[checksummed..])?;
+    if checksum != fnv1a(&data[..checksummed]) {
+        return Err(ComparerError::Corrupt("checksum mismatch".to_string()));
+    }
+    let bytes = &mut &data[MAGIC.len() + 2..checksummed];
+    let seeded = u8::decode(bytes)? & SEEDED != 0;
+    let generation = u64::decode(bytes)?;
+    let count = u64::decode(bytes)?;
+    let mut map = HashMap::new();
+    for _ in 0..count {
+        let key = decode_entry(bytes)?;
+        let value = decode_entry(bytes)?;
+        map.insert(key, value);
+    }
+    if !bytes.is_empty() {
+        return Err(ComparerError::Corrupt(
+            "trailing data after entries".to_string(),
+        ));
+    }
+    Ok(Snapshot {
+        map,
+        seeded,
+        generation,
+    })
+}
+
+/// Decodes a length prefixed key or value, which has to use up exactly its bytes
+fn decode_entry<T: Decode>(bytes: &mut &[u8]) -> Result<T, ComparerError> {
+    let len = decode_len(bytes)?;
+    let entry = &mut take(bytes, len)?;
+    let decoded = T::decode(entry)?;
+    if !entry.is_empty() {
+        return Err(ComparerError::Corrupt("entry has unread bytes".to_string()));
+    }
+    Ok(decoded)
+}
+
+/// 64 bit FNV-1a hash
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
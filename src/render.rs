@@ -0,0 +1,145 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+
+use crate::{Change, ChangeSet};
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Human readable report of a change set, returned by `ChangeSet::render()`
+///
+/// Every change is a line: `+ key: value` for added keys, `- key: value` for removed ones
+/// and `~ key: old -> new` for modified ones. Keys of `FirstRun::Snapshot` are shown as added.
+/// Lines are sorted lexicographically by the formatted key, so the report is the same on every run,
+/// but integer keys come out as `1, 10, 2`. Use `with_key_order()` to sort by the keys themselves.
+/// The last line counts the changes.
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::HashMapComparer;
+///
+///   let comparer = HashMapComparer::<&str, &str>::new();
+///   comparer.update(&HashMap::from([("b", "old"), ("c", "gone")]));
+///   let changes = comparer.diff(&HashMap::from([("b", "new"), ("a", "a very long value")]));
+///
+///   assert_eq!(
+///       "+ a: a very long value\n~ b: old -> new\n- c: gone\n1 added, 1 removed, 1 modified",
+///       changes.to_string()
+///   );
+///   assert_eq!(
+///       "+ a: a very…\n~ b: old -> new\n- c: gone\n1 added, 1 removed, 1 modified",
+///       changes.render().with_width(6).to_string()
+///   );
+///   assert!(changes.render().with_color(true).to_string().starts_with("\x1b[32m+ a"));
+/// ```
+/// Sorting by key:
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::HashMapComparer;
+///
+///   let comparer = HashMapComparer::<u32, &str>::new();
+///   let changes = comparer.diff(&HashMap::from([(1, "foo"), (2, "bar"), (10, "baz")]));
+///   assert!(changes.to_string().starts_with("+ 1: foo\n+ 10: baz\n+ 2: bar"));
+///   assert!(changes.render().with_key_order().to_string().starts_with("+ 1: foo\n+ 2: bar\n+ 10: baz"));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Render<'a, K, V> {
+    changes: &'a ChangeSet<K, V>,
+    color: bool,
+    width: Option<usize>,
+    order: Option<fn(&K, &K) -> Ordering>,
+}
+
+impl<K, V> ChangeSet<K, V> {
+    /// Returns a human readable report of the changes, see `Render`.
+    /// `Display` of a change set is the same report without colour and truncation.
+    pub fn render(&self) -> Render<'_, K, V> {
+        Render {
+            changes: self,
+            color: false,
+            width: None,
+            order: None,
+        }
+    }
+}
+
+impl<K, V> Render<'_, K, V> {
+    /// Colours lines with ANSI escape codes: added green, removed red and modified yellow
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Cuts values longer than `width` characters, marking the cut with `…`
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    fn value(&self, value: &impl Display) -> String {
+        let value = value.to_string();
+        match self.width {
+            Some(width) if value.chars().count() > width => {
+                let mut cut: String = value.chars().take(width).collect();
+                cut.push('…');
+                cut
+            }
+            _ => value,
+        }
+    }
+}
+
+impl<K: Ord, V> Render<'_, K, V> {
+    /// Sorts lines by the keys themselves instead of their formatted strings, like `ChangeSet::sort()`
+    pub fn with_key_order(mut self) -> Self {
+        self.order = Some(K::cmp);
+        self
+    }
+}
+
+impl<K: Display, V: Display> Display for Render<'_, K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines: Vec<(&K, String, &Change<V>)> = self
+            .changes
+            .iter()
+            .map(|(key, change)| (key, key.to_string(), change))
+            .collect();
+        match self.order {
+            Some(order) => lines.sort_by(|(a, _, _), (b, _, _)| order(a, b)),
+            None => lines.sort_by(|(_, a, _), (_, b, _)| a.cmp(b)),
+        }
+
+        let (mut added, mut removed, mut modified) = (0, 0, 0);
+        for (_, key, change) in lines {
+            let (color, line) = match change {
+                Change::Added(new) | Change::Initial(new) => {
+                    added += 1;
+                    (GREEN, format!("+ {key}: {}", self.value(new)))
+                }
+                Change::Removed(old) => {
+                    removed += 1;
+                    (RED, format!("- {key}: {}", self.value(old)))
+                }
+                Change::Modified { old, new } => {
+                    modified += 1;
+                    let (old, new) = (self.value(old), self.value(new));
+                    (YELLOW, format!("~ {key}: {old} -> {new}"))
+                }
+            };
+            if self.color {
+                writeln!(f, "{color}{line}{RESET}")?;
+            } else {
+                writeln!(f, "{line}")?;
+            }
+        }
+        write!(f, "{added} added, {removed} removed, {modified} modified")
+    }
+}
+
+impl<K: Display, V: Display> Display for ChangeSet<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render().fmt(f)
+    }
+}
@@ -0,0 +1,454 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::shared::{Last, Shared};
+use crate::{or_panic, ComparerError, Equality, PoisonPolicy};
+
+/// Single step of an edit script that turns the last vector into a new one, returned by `VecComparer`
+///
+/// Indices of removed elements refer to the last vector, every other index refers to the new vector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit<T> {
+    /// Element exists only in the new vector
+    Insert { index: usize, value: T },
+    /// Element exists only in the last vector
+    Delete { index: usize, value: T },
+    /// Element with the same key moved, only reported in keyed mode, see `VecComparer::with_key()`
+    Move { from: usize, to: usize, value: T },
+    /// Element with the same key has a different value, only reported in keyed mode
+    Modify { index: usize, old: T, new: T },
+}
+
+/// Comparer for ordered lists that reports a minimal edit script of inserted and deleted elements
+///
+/// Edit scripts are computed with the linear space variant of the Myers diff algorithm
+/// in O((N + M) * D) time and O(N + M) memory, where D is the number of edits.
+/// Like `HashMapComparer`, clones share last vector and readers don't wait for a running update.
+/// # Examples
+/// ```
+///   use comparer::{Edit, VecComparer};
+///
+///   let comparer = VecComparer::<char>::new();
+///   comparer.update(&"abcabba".chars().collect::<Vec<_>>());
+///
+///   let edits = comparer.update_and_compare(&"cbabac".chars().collect::<Vec<_>>());
+///   assert_eq!(5, edits.len());
+///   assert_eq!(Edit::Delete { index: 0, value: 'a' }, edits[0]);
+///   assert!(comparer.is_same(&"cbabac".chars().collect::<Vec<_>>()));
+/// ```
+/// Keyed mode matches elements by a key, so moved and modified elements are reported as such:
+/// ```
+///   use comparer::{Edit, VecComparer};
+///
+///   let comparer = VecComparer::<(u32, &str)>::with_key(|(id, _)| *id);
+///   comparer.update(&vec![(1, "foo"), (2, "bar"), (3, "baz")]);
+///
+///   let edits = comparer.compare(&vec![(2, "bar"), (3, "qux"), (1, "foo")]);
+///   assert_eq!(
+///       vec![
+///           Edit::Move { from: 0, to: 2, value: (1, "foo") },
+///           Edit::Modify { index: 1, old: (3, "baz"), new: (3, "qux") },
+///       ],
+///       edits
+///   );
+///
+///   // Element moved to the front is reported once
+///   let edits = comparer.compare(&vec![(3, "baz"), (1, "foo"), (2, "bar")]);
+///   assert_eq!(vec![Edit::Move { from: 2, to: 0, value: (3, "baz") }], edits);
+/// ```
+#[derive(Clone)]
+pub struct VecComparer<T> {
+    last: Shared<Last<Vec<T>>>,
+    same_key: Option<Equality<T>>,
+}
+
+/// Step of an edit script before it is turned into `Edit`s
+enum Step {
+    Keep(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+impl<T: Clone + PartialEq> VecComparer<T> {
+    pub fn new() -> Self {
+        Self {
+            last: Shared::new(Last::default()),
+            same_key: None,
+        }
+    }
+
+    /// Creates comparer that matches elements by the key `key` extracts instead of by value.
+    /// Elements with the same key that are out of order are reported as `Edit::Move`,
+    /// elements with the same key and a different value as `Edit::Modify`.
+    /// Pairing moved elements takes O(deleted * inserted) key comparisons.
+    pub fn with_key<K: PartialEq>(key: impl Fn(&T) -> K + Send + Sync + 'static) -> Self {
+        Self {
+            same_key: Some(Arc::new(move |a, b| key(a) == key(b))),
+            ..Self::new()
+        }
+    }
+
+    /// Sets what happens after a thread panicked while updating the comparer, e.g. in `PartialEq` of an element,
+    /// see `HashMapComparer::with_poison_policy()` for an example
+    pub fn with_poison_policy(self, poison_policy: PoisonPolicy) -> Self {
+        self.last.set_poison_policy(poison_policy);
+        self
+    }
+
+    /// Clones last vector
+    pub fn clone_last(&self) -> Vec<T> {
+        or_panic(self.try_clone_last())
+    }
+
+    /// Same as `clone_last()`, but returns an error instead of panicking
+    pub fn try_clone_last(&self) -> Result<Vec<T>, ComparerError> {
+        self.last.read(Vec::clone)
+    }
+
+    /// Returns true if last vector was set at least once
+    pub fn is_seeded(&self) -> bool {
+        or_panic(self.try_is_seeded())
+    }
+
+    /// Same as `is_seeded()`, but returns an error instead of panicking
+    pub fn try_is_seeded(&self) -> Result<bool, ComparerError> {
+        Ok(self.last.current()?.1)
+    }
+
+    /// Checks if last vector has the same elements in the same order as the new one
+    pub fn is_same(&self, comparable: &[T]) -> bool {
+        or_panic(self.try_is_same(comparable))
+    }
+
+    /// Same as `is_same()`, but returns an error instead of panicking
+    pub fn try_is_same(&self, comparable: &[T]) -> Result<bool, ComparerError> {
+        self.last.read(|last| *last == comparable)
+    }
+
+    /// Updates last vector to a new value
+    pub fn update(&self, new_items: &[T]) {
+        or_panic(self.try_update(new_items))
+    }
+
+    /// Same as `update()`, but returns an error instead of panicking
+    pub fn try_update(&self, new_items: &[T]) -> Result<(), ComparerError> {
+        self.last.update(|_| ((), new_items.to_vec()))
+    }
+
+    /// Checks if last vector is the same as new one and updates it to be that new value
+    pub fn is_same_update(&self, new_items: &[T]) -> bool {
+        or_panic(self.try_is_same_update(new_items))
+    }
+
+    /// Same as `is_same_update()`, but returns an error instead of panicking
+    pub fn try_is_same_update(&self, new_items: &[T]) -> Result<bool, ComparerError> {
+        self.last
+            .update(|last| (*last == new_items, new_items.to_vec()))
+    }
+
+    /// Returns edits that turn last vector into the new one. The first comparison inserts every element.
+    /// # Examples
+    /// ```
+    ///   use comparer::{Edit, VecComparer};
+    ///
+    ///   // Removes deleted elements, then inserts new ones in order
+    ///   fn apply(old: &[u32], edits: &[Edit<u32>]) -> Vec<u32> {
+    ///       let mut items: Vec<Option<u32>> = old.iter().copied().map(Some).collect();
+    ///       for edit in edits {
+    ///           if let Edit::Delete { index, .. } = edit {
+    ///               items[*index] = None;
+    ///           }
+    ///       }
+    ///       let mut items: Vec<u32> = items.into_iter().flatten().collect();
+    ///       for edit in edits {
+    ///           if let Edit::Insert { index, value } = edit {
+    ///               items.insert(*index, *value);
+    ///           }
+    ///       }
+    ///       items
+    ///   }
+    ///
+    ///   let comparer = VecComparer::<u32>::new();
+    ///   assert!(comparer.compare(&[]).is_empty());
+    ///   assert_eq!(vec![Edit::Insert { index: 0, value: 1 }], comparer.compare(&[1]));
+    ///
+    ///   let old = vec![1, 2, 3, 4, 5, 6];
+    ///   comparer.update(&old);
+    ///   assert!(comparer.compare(&old).is_empty());
+    ///   assert_eq!(6, comparer.compare(&[]).len());
+    ///   assert_eq!(12, comparer.compare(&[7, 8, 9, 10, 11, 12]).len());
+    ///
+    ///   for new in [vec![], vec![6, 5, 4, 3, 2, 1], vec![0, 2, 4, 6, 8], vec![1, 1, 3, 3, 5, 5, 7], vec![9; 3]] {
+    ///       assert_eq!(new, apply(&old, &comparer.compare(&new)));
+    ///   }
+    /// ```
+    pub fn compare(&self, new_items: &[T]) -> Vec<Edit<T>> {
+        or_panic(self.try_compare(new_items))
+    }
+
+    /// Same as `compare()`, but returns an error instead of panicking
+    pub fn try_compare(&self, new_items: &[T]) -> Result<Vec<Edit<T>>, ComparerError> {
+        self.last.read(|last| self.edits(last, new_items))
+    }
+
+    /// Updates last vector and returns edits that turn the previous one into it, see `compare()`.
+    /// Edits are computed against a snapshot, readers don't wait for them.
+    pub fn update_and_compare(&self, new_items: &[T]) -> Vec<Edit<T>> {
+        or_panic(self.try_update_and_compare(new_items))
+    }
+
+    /// Same as `update_and_compare()`, but returns an error instead of panicking
+    pub fn try_update_and_compare(&self, new_items: &[T]) -> Result<Vec<Edit<T>>, ComparerError> {
+        self.last
+            .update(|last| (self.edits(last, new_items), new_items.to_vec()))
+    }
+
+    fn edits(&self, old: &[T], new: &[T]) -> Vec<Edit<T>> {
+        match &self.same_key {
+            None => myers(old.len(), new.len(), |i, j| old[i] == new[j])
+                .into_iter()
+                .filter_map(|step| match step {
+                    Step::Keep(..) => None,
+                    Step::Delete(index) => Some(Edit::Delete {
+                        index,
+                        value: old[index].clone(),
+                    }),
+                    Step::Insert(index) => Some(Edit::Insert {
+                        index,
+                        value: new[index].clone(),
+                    }),
+                })
+                .collect(),
+            Some(same_key) => keyed_edits(old, new, &**same_key),
+        }
+    }
+}
+
+/// Turns an edit script over keys into edits, pairing deleted and inserted elements with the same key into moves
+fn keyed_edits<T: Clone + PartialEq>(
+    old: &[T],
+    new: &[T],
+    same_key: &(dyn Fn(&T, &T) -> bool + Send + Sync),
+) -> Vec<Edit<T>> {
+    let steps = myers(old.len(), new.len(), |i, j| same_key(&old[i], &new[j]));
+    // Pair every deleted element with the first inserted one of the same key before emitting edits,
+    // an insert can come before its delete in the script
+    let mut inserted: Vec<usize> = steps
+        .iter()
+        .filter_map(|step| match step {
+            Step::Insert(index) => Some(*index),
+            _ => None,
+        })
+        .collect();
+    let mut moved_from = vec![None; old.len()];
+    let mut moved_to = vec![false; new.len()];
+    for step in &steps {
+        if let Step::Delete(from) = *step {
+            if let Some(position) = inserted
+                .iter()
+                .position(|to| same_key(&old[from], &new[*to]))
+            {
+                let to = inserted.remove(position);
+                moved_from[from] = Some(to);
+                moved_to[to] = true;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let modify = |edits: &mut Vec<Edit<T>>, from: usize, to: usize| {
+        if old[from] != new[to] {
+            edits.push(Edit::Modify {
+                index: to,
+                old: old[from].clone(),
+                new: new[to].clone(),
+            });
+        }
+    };
+    for step in &steps {
+        match *step {
+            Step::Keep(from, to) => modify(&mut edits, from, to),
+            Step::Delete(from) => match moved_from[from] {
+                Some(to) => {
+                    edits.push(Edit::Move {
+                        from,
+                        to,
+                        value: new[to].clone(),
+                    });
+                    modify(&mut edits, from, to);
+                }
+                None => edits.push(Edit::Delete {
+                    index: from,
+                    value: old[from].clone(),
+                }),
+            },
+            Step::Insert(to) if !moved_to[to] => edits.push(Edit::Insert {
+                index: to,
+                value: new[to].clone(),
+            }),
+            Step::Insert(_) => {}
+        }
+    }
+    edits
+}
+
+/// Shortest edit script between sequences of length `n` and `m`, `eq(i, j)` compares their elements.
+/// Uses the linear space variant: finds the middle snake of the script and recurses into the halves
+/// before and after it, so only two vectors of diagonals are kept at a time.
+fn myers(n: usize, m: usize, eq: impl Fn(usize, usize) -> bool) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut forward = Vec::new();
+    let mut backward = Vec::new();
+    split(&eq, (0, n), (0, m), &mut forward, &mut backward, &mut steps);
+    steps
+}
+
+/// Appends the edit script between `old.0..old.1` and `new.0..new.1`
+fn split(
+    eq: &impl Fn(usize, usize) -> bool,
+    (mut old_start, mut old_end): (usize, usize),
+    (mut new_start, mut new_end): (usize, usize),
+    forward: &mut Vec<isize>,
+    backward: &mut Vec<isize>,
+    steps: &mut Vec<Step>,
+) {
+    while old_start < old_end && new_start < new_end && eq(old_start, new_start) {
+        steps.push(Step::Keep(old_start, new_start));
+        old_start += 1;
+        new_start += 1;
+    }
+    let mut suffix = 0;
+    while old_start < old_end && new_start < new_end && eq(old_end - 1, new_end - 1) {
+        old_end -= 1;
+        new_end -= 1;
+        suffix += 1;
+    }
+
+    if old_start == old_end {
+        steps.extend((new_start..new_end).map(Step::Insert));
+    } else if new_start == new_end {
+        steps.extend((old_start..old_end).map(Step::Delete));
+    } else {
+        let (x, y) = middle_snake(
+            eq,
+            (old_start, old_end),
+            (new_start, new_end),
+            forward,
+            backward,
+        );
+        split(eq, (old_start, x), (new_start, y), forward, backward, steps);
+        split(eq, (x, old_end), (y, new_end), forward, backward, steps);
+    }
+
+    steps.extend((0..suffix).map(|i| Step::Keep(old_end + i, new_end + i)));
+}
+
+/// Searches the shortest edit script from both ends at once until the paths overlap,
+/// returns the point where they meet. Both ranges have to be non-empty.
+fn middle_snake(
+    eq: &impl Fn(usize, usize) -> bool,
+    (old_start, old_end): (usize, usize),
+    (new_start, new_end): (usize, usize),
+    forward: &mut Vec<isize>,
+    backward: &mut Vec<isize>,
+) -> (usize, usize) {
+    let (n, m) = (
+        (old_end - old_start) as isize,
+        (new_end - new_start) as isize,
+    );
+    let max = (n + m + 1) / 2;
+    let offset = max;
+    let len = 2 * max + 2;
+    forward.clear();
+    forward.resize(len as usize, -1);
+    backward.clear();
+    backward.resize(len as usize, -1);
+    forward[(offset + 1) as usize] = 0;
+    backward[(offset + 1) as usize] = 0;
+    let delta = n - m;
+    // With an odd delta the forward path reaches the overlap first, otherwise the backward one
+    let odd = delta % 2 != 0;
+    let same = |x: isize, y: isize| eq(old_start + x as usize, new_start + y as usize);
+    let same_back = |x: isize, y: isize| eq(old_end - 1 - x as usize, new_end - 1 - y as usize);
+    // Diagonals that ran off the edge of the grid are skipped in later rounds
+    let (mut forward_start, mut forward_end, mut backward_start, mut backward_end) = (0, 0, 0, 0);
+
+    for d in 0..max {
+        for k in (-d + forward_start..=d - forward_end).step_by(2) {
+            let index = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && forward[index - 1] < forward[index + 1]) {
+                forward[index + 1]
+            } else {
+                forward[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && same(x, y) {
+                x += 1;
+                y += 1;
+            }
+            forward[index] = x;
+            if x > n {
+                forward_end += 2;
+            } else if y > m {
+                forward_start += 2;
+            } else if odd {
+                let mirrored = offset + delta - k;
+                if (0..len).contains(&mirrored) && backward[mirrored as usize] != -1 {
+                    let backward_x = n - backward[mirrored as usize];
+                    if x >= backward_x {
+                        return (old_start + x as usize, new_start + y as usize);
+                    }
+                }
+            }
+        }
+
+        for k in (-d + backward_start..=d - backward_end).step_by(2) {
+            let index = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && backward[index - 1] < backward[index + 1]) {
+                backward[index + 1]
+            } else {
+                backward[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && same_back(x, y) {
+                x += 1;
+                y += 1;
+            }
+            backward[index] = x;
+            if x > n {
+                backward_end += 2;
+            } else if y > m {
+                backward_start += 2;
+            } else if !odd {
+                let mirrored = offset + delta - k;
+                if (0..len).contains(&mirrored) && forward[mirrored as usize] != -1 {
+                    let forward_x = forward[mirrored as usize];
+                    let forward_y = forward_x - (mirrored - offset);
+                    if forward_x >= n - x {
+                        return (
+                            old_start + forward_x as usize,
+                            new_start + forward_y as usize,
+                        );
+                    }
+                }
+            }
+        }
+    }
+    // Paths always meet within `max` rounds, splitting at the corner would still be correct
+    (old_end, new_start)
+}
+
+impl<T: Clone + PartialEq> Default for VecComparer<T> {
+    fn default() -> Self {
+        VecComparer::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for VecComparer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VecComparer")
+            .field("last", &self.last)
+            .field("keyed", &self.same_key.is_some())
+            .finish()
+    }
+}
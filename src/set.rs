@@ -0,0 +1,403 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+
+use crate::shared::{Last, Shared};
+use crate::{or_panic, ComparerError, PoisonPolicy};
+
+/// Members that changed between last set and a new one, returned by `HashSetComparer`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetChanges<T: Eq + Hash> {
+    /// Members that exist only in the new set
+    pub added: HashSet<T>,
+    /// Members that exist only in the last set
+    pub removed: HashSet<T>,
+}
+
+impl<T: Eq + Hash> SetChanges<T> {
+    /// Creates empty set changes
+    pub fn new() -> Self {
+        Self {
+            added: HashSet::new(),
+            removed: HashSet::new(),
+        }
+    }
+
+    /// Returns true if no member was added or removed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl<T: Eq + Hash> Default for SetChanges<T> {
+    fn default() -> Self {
+        SetChanges::new()
+    }
+}
+
+/// Comparer for sets that reports added and removed members
+///
+/// Works like `HashMapComparer` for a set: clones share last set, readers don't wait for a running update
+/// and the first comparison reports every member as added.
+/// # Examples
+/// ```
+///   use std::collections::HashSet;
+///   use comparer::HashSetComparer;
+///
+///   let comparer = HashSetComparer::<u32>::new();
+///   comparer.update(&HashSet::from([1, 2, 3]));
+///
+///   let changes = comparer.update_and_compare(&HashSet::from([2, 3, 4]));
+///   assert_eq!(HashSet::from([4]), changes.added);
+///   assert_eq!(HashSet::from([1]), changes.removed);
+///   assert!(comparer.is_same(&HashSet::from([4, 3, 2])));
+/// ```
+#[derive(Clone)]
+pub struct HashSetComparer<T> {
+    last: Shared<Last<HashSet<T>>>,
+}
+
+impl<T: Clone + Eq + Hash> HashSetComparer<T> {
+    pub fn new() -> Self {
+        Self {
+            last: Shared::new(Last::default()),
+        }
+    }
+
+    /// Sets what happens after a thread panicked while updating the comparer, e.g. in `Hash` or `Eq` of a member,
+    /// see `HashMapComparer::with_poison_policy()` for an example
+    pub fn with_poison_policy(self, poison_policy: PoisonPolicy) -> Self {
+        self.last.set_poison_policy(poison_policy);
+        self
+    }
+
+    /// Clones last set
+    pub fn clone_last(&self) -> HashSet<T> {
+        or_panic(self.try_clone_last())
+    }
+
+    /// Same as `clone_last()`, but returns an error instead of panicking
+    pub fn try_clone_last(&self) -> Result<HashSet<T>, ComparerError> {
+        self.last.read(HashSet::clone)
+    }
+
+    /// Returns true if last set was set at least once
+    pub fn is_seeded(&self) -> bool {
+        or_panic(self.try_is_seeded())
+    }
+
+    /// Same as `is_seeded()`, but returns an error instead of panicking
+    pub fn try_is_seeded(&self) -> Result<bool, ComparerError> {
+        Ok(self.last.current()?.1)
+    }
+
+    /// Checks if last set has the same members as the new one
+    pub fn is_same<S: BuildHasher>(&self, comparable: &HashSet<T, S>) -> bool {
+        or_panic(self.try_is_same(comparable))
+    }
+
+    /// Same as `is_same()`, but returns an error instead of panicking
+    pub fn try_is_same<S: BuildHasher>(
+        &self,
+        comparable: &HashSet<T, S>,
+    ) -> Result<bool, ComparerError> {
+        self.last.read(|last| same_sets(last, comparable))
+    }
+
+    /// Updates last set to a new value
+    pub fn update<S: BuildHasher>(&self, new_set: &HashSet<T, S>) {
+        or_panic(self.try_update(new_set))
+    }
+
+    /// Same as `update()`, but returns an error instead of panicking
+    pub fn try_update<S: BuildHasher>(&self, new_set: &HashSet<T, S>) -> Result<(), ComparerError> {
+        self.last
+            .update(|_| ((), new_set.iter().cloned().collect()))
+    }
+
+    /// Checks if last set is the same as new one and updates it to be that new value
+    pub fn is_same_update<S: BuildHasher>(&self, new_set: &HashSet<T, S>) -> bool {
+        or_panic(self.try_is_same_update(new_set))
+    }
+
+    /// Same as `is_same_update()`, but returns an error instead of panicking
+    pub fn try_is_same_update<S: BuildHasher>(
+        &self,
+        new_set: &HashSet<T, S>,
+    ) -> Result<bool, ComparerError> {
+        self.last
+            .update(|last| (same_sets(last, new_set), new_set.iter().cloned().collect()))
+    }
+
+    /// Compares new set to the last one and returns added and removed members
+    pub fn compare<S: BuildHasher>(&self, new_set: &HashSet<T, S>) -> SetChanges<T> {
+        or_panic(self.try_compare(new_set))
+    }
+
+    /// Same as `compare()`, but returns an error instead of panicking
+    pub fn try_compare<S: BuildHasher>(
+        &self,
+        new_set: &HashSet<T, S>,
+    ) -> Result<SetChanges<T>, ComparerError> {
+        self.last.read(|last| set_changes(last, new_set))
+    }
+
+    /// Updates last set and returns added and removed members, see `compare()`
+    pub fn update_and_compare<S: BuildHasher>(&self, new_set: &HashSet<T, S>) -> SetChanges<T> {
+        or_panic(self.try_update_and_compare(new_set))
+    }
+
+    /// Same as `update_and_compare()`, but returns an error instead of panicking
+    pub fn try_update_and_compare<S: BuildHasher>(
+        &self,
+        new_set: &HashSet<T, S>,
+    ) -> Result<SetChanges<T>, ComparerError> {
+        self.last.update(|last| {
+            (
+                set_changes(last, new_set),
+                new_set.iter().cloned().collect(),
+            )
+        })
+    }
+}
+
+fn same_sets<T: Eq + Hash, S: BuildHasher>(last: &HashSet<T>, new_set: &HashSet<T, S>) -> bool {
+    last.len() == new_set.len() && last.iter().all(|member| new_set.contains(member))
+}
+
+fn set_changes<T: Clone + Eq + Hash, S: BuildHasher>(
+    last: &HashSet<T>,
+    new_set: &HashSet<T, S>,
+) -> SetChanges<T> {
+    SetChanges {
+        added: new_set
+            .iter()
+            .filter(|member| !last.contains(*member))
+            .cloned()
+            .collect(),
+        removed: last
+            .iter()
+            .filter(|member| !new_set.contains(*member))
+            .cloned()
+            .collect(),
+    }
+}
+
+impl<T: Clone + Eq + Hash> Default for HashSetComparer<T> {
+    fn default() -> Self {
+        HashSetComparer::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for HashSetComparer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HashSetComparer")
+            .field("last", &self.last)
+            .finish()
+    }
+}
+
+/// Comparer for multisets, i.e. collections with duplicates, that reports how the count of every item changed
+///
+/// New multisets are passed as anything that iterates over references to items, e.g. `&Vec<T>` or `&[T]`.
+/// Items are counted before any lock is taken, so their order doesn't matter.
+/// Like `HashMapComparer`, clones share last multiset and readers don't wait for a running update.
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::MultisetComparer;
+///
+///   let comparer = MultisetComparer::<&str>::new();
+///   comparer.update(&["rust", "rust", "go"]);
+///   assert!(comparer.is_same(&["go", "rust", "rust"]));
+///
+///   let deltas = comparer.update_and_compare(&["rust", "zig", "zig"]);
+///   assert_eq!(HashMap::from([("rust", -1), ("go", -1), ("zig", 2)]), deltas);
+///   assert_eq!(HashMap::from([("rust", 1), ("zig", 2)]), comparer.clone_last());
+/// ```
+#[derive(Clone)]
+pub struct MultisetComparer<T> {
+    last: Shared<Last<HashMap<T, usize>>>,
+}
+
+impl<T: Clone + Eq + Hash> MultisetComparer<T> {
+    pub fn new() -> Self {
+        Self {
+            last: Shared::new(Last::default()),
+        }
+    }
+
+    /// Sets what happens after a thread panicked while updating the comparer, e.g. in `Hash` or `Eq` of an item,
+    /// see `HashMapComparer::with_poison_policy()`
+    pub fn with_poison_policy(self, poison_policy: PoisonPolicy) -> Self {
+        self.last.set_poison_policy(poison_policy);
+        self
+    }
+
+    /// Clones count of every item of last multiset
+    pub fn clone_last(&self) -> HashMap<T, usize> {
+        or_panic(self.try_clone_last())
+    }
+
+    /// Same as `clone_last()`, but returns an error instead of panicking
+    pub fn try_clone_last(&self) -> Result<HashMap<T, usize>, ComparerError> {
+        self.last.read(HashMap::clone)
+    }
+
+    /// Returns true if last multiset was set at least once
+    pub fn is_seeded(&self) -> bool {
+        or_panic(self.try_is_seeded())
+    }
+
+    /// Same as `is_seeded()`, but returns an error instead of panicking
+    pub fn try_is_seeded(&self) -> Result<bool, ComparerError> {
+        Ok(self.last.current()?.1)
+    }
+
+    /// Checks if last multiset has every item as many times as the new one
+    pub fn is_same<'a>(&self, comparable: impl IntoIterator<Item = &'a T>) -> bool
+    where
+        T: 'a,
+    {
+        or_panic(self.try_is_same(comparable))
+    }
+
+    /// Same as `is_same()`, but returns an error instead of panicking
+    pub fn try_is_same<'a>(
+        &self,
+        comparable: impl IntoIterator<Item = &'a T>,
+    ) -> Result<bool, ComparerError>
+    where
+        T: 'a,
+    {
+        let counts = counts(comparable);
+        self.last.read(|last| *last == counts)
+    }
+
+    /// Updates last multiset to a new value
+    pub fn update<'a>(&self, new_items: impl IntoIterator<Item = &'a T>)
+    where
+        T: 'a,
+    {
+        or_panic(self.try_update(new_items))
+    }
+
+    /// Same as `update()`, but returns an error instead of panicking
+    pub fn try_update<'a>(
+        &self,
+        new_items: impl IntoIterator<Item = &'a T>,
+    ) -> Result<(), ComparerError>
+    where
+        T: 'a,
+    {
+        let counts = counts(new_items);
+        self.last.update(|_| ((), counts))
+    }
+
+    /// Checks if last multiset is the same as new one and updates it to be that new value
+    pub fn is_same_update<'a>(&self, new_items: impl IntoIterator<Item = &'a T>) -> bool
+    where
+        T: 'a,
+    {
+        or_panic(self.try_is_same_update(new_items))
+    }
+
+    /// Same as `is_same_update()`, but returns an error instead of panicking
+    pub fn try_is_same_update<'a>(
+        &self,
+        new_items: impl IntoIterator<Item = &'a T>,
+    ) -> Result<bool, ComparerError>
+    where
+        T: 'a,
+    {
+        let counts = counts(new_items);
+        self.last.update(|last| (*last == counts, counts))
+    }
+
+    /// Compares new multiset to the last one and returns how the count of every changed item moved.
+    /// Items whose count didn't change are left out.
+    pub fn compare<'a>(&self, new_items: impl IntoIterator<Item = &'a T>) -> HashMap<T, isize>
+    where
+        T: 'a,
+    {
+        or_panic(self.try_compare(new_items))
+    }
+
+    /// Same as `compare()`, but returns an error instead of panicking
+    pub fn try_compare<'a>(
+        &self,
+        new_items: impl IntoIterator<Item = &'a T>,
+    ) -> Result<HashMap<T, isize>, ComparerError>
+    where
+        T: 'a,
+    {
+        let counts = counts(new_items);
+        self.last.read(|last| deltas(last, &counts))
+    }
+
+    /// Updates last multiset and returns count changes, see `compare()`
+    pub fn update_and_compare<'a>(
+        &self,
+        new_items: impl IntoIterator<Item = &'a T>,
+    ) -> HashMap<T, isize>
+    where
+        T: 'a,
+    {
+        or_panic(self.try_update_and_compare(new_items))
+    }
+
+    /// Same as `update_and_compare()`, but returns an error instead of panicking
+    pub fn try_update_and_compare<'a>(
+        &self,
+        new_items: impl IntoIterator<Item = &'a T>,
+    ) -> Result<HashMap<T, isize>, ComparerError>
+    where
+        T: 'a,
+    {
+        let counts = counts(new_items);
+        self.last.update(|last| (deltas(last, &counts), counts))
+    }
+}
+
+fn counts<'a, T: Clone + Eq + Hash + 'a>(
+    items: impl IntoIterator<Item = &'a T>,
+) -> HashMap<T, usize> {
+    let mut counts = HashMap::new();
+    for item in items {
+        *counts.entry(item.clone()).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn deltas<T: Clone + Eq + Hash>(
+    last: &HashMap<T, usize>,
+    new_counts: &HashMap<T, usize>,
+) -> HashMap<T, isize> {
+    let mut deltas = HashMap::new();
+    for (item, count) in new_counts {
+        let old = last.get(item).copied().unwrap_or(0);
+        if old != *count {
+            deltas.insert(item.clone(), *count as isize - old as isize);
+        }
+    }
+    for (item, count) in last {
+        if !new_counts.contains_key(item) {
+            deltas.insert(item.clone(), -(*count as isize));
+        }
+    }
+    deltas
+}
+
+impl<T: Clone + Eq + Hash> Default for MultisetComparer<T> {
+    fn default() -> Self {
+        MultisetComparer::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MultisetComparer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MultisetComparer")
+            .field("last", &self.last)
+            .finish()
+    }
+}
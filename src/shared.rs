@@ -0,0 +1,115 @@
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::error::SharedPoisonPolicy;
+use crate::{ComparerError, PoisonPolicy};
+
+/// State of a comparer together with the locks every comparer uses, shared by all its handles
+///
+/// The state only holds immutable `Arc` snapshots, so it is locked just to read or swap them.
+/// Updates compare against a snapshot and swap in the result, they are serialized by `writer`.
+pub(crate) struct Shared<S> {
+    state: Arc<Mutex<S>>,
+    /// Held for the whole update, so updates don't interleave
+    writer: Arc<Mutex<()>>,
+    poison_policy: SharedPoisonPolicy,
+}
+
+impl<S> Shared<S> {
+    pub(crate) fn new(state: S) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(state)),
+            writer: Arc::new(Mutex::new(())),
+            poison_policy: SharedPoisonPolicy::default(),
+        }
+    }
+
+    /// Independent state with a copy of the poison policy, e.g. for a fork
+    pub(crate) fn fork(&self, state: S) -> Self {
+        Self {
+            poison_policy: self.poison_policy.detach(),
+            ..Self::new(state)
+        }
+    }
+
+    pub(crate) fn set_poison_policy(&self, poison_policy: PoisonPolicy) {
+        self.poison_policy.set(poison_policy);
+    }
+
+    /// Locks the state, following the poison policy if the lock is poisoned
+    pub(crate) fn lock(&self) -> Result<MutexGuard<'_, S>, ComparerError> {
+        self.poison_policy.recover(&self.state, self.state.lock())
+    }
+
+    /// Locks out other updates, following the poison policy if an update panicked
+    pub(crate) fn lock_writer(&self) -> Result<MutexGuard<'_, ()>, ComparerError> {
+        self.poison_policy.recover(&self.writer, self.writer.lock())
+    }
+
+    /// Releases the state until `changed` is notified or `timeout` elapses, then locks it again
+    pub(crate) fn wait<'a>(
+        &self,
+        changed: &Condvar,
+        state: MutexGuard<'a, S>,
+        timeout: Duration,
+    ) -> Result<MutexGuard<'a, S>, ComparerError> {
+        let result = changed.wait_timeout(state, timeout);
+        Ok(self.poison_policy.recover(&self.state, result)?.0)
+    }
+}
+
+/// Clones share the state
+impl<S> Clone for Shared<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            writer: self.writer.clone(),
+            poison_policy: self.poison_policy.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for Shared<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Shared")
+            .field("state", &self.state)
+            .field("poison_policy", &self.poison_policy)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Last collection of `VecComparer`, `HashSetComparer`, `MultisetComparer` and `FingerprintComparer`
+#[derive(Debug, Default)]
+pub(crate) struct Last<C> {
+    items: Arc<C>,
+    seeded: bool,
+}
+
+impl<C> Shared<Last<C>> {
+    /// Snapshot of last collection and whether it was ever set
+    pub(crate) fn current(&self) -> Result<(Arc<C>, bool), ComparerError> {
+        let last = self.lock()?;
+        Ok((last.items.clone(), last.seeded))
+    }
+
+    /// Compares against a snapshot of last collection without waiting for a running update
+    pub(crate) fn read<R>(&self, compare: impl FnOnce(&C) -> R) -> Result<R, ComparerError> {
+        Ok(compare(&self.current()?.0))
+    }
+
+    /// Compares against last collection and replaces it with the new collection `update` returns
+    /// besides the result. Other updates wait, readers keep using the old collection until it is swapped.
+    pub(crate) fn update<R>(&self, update: impl FnOnce(&C) -> (R, C)) -> Result<R, ComparerError> {
+        let writer = self.lock_writer()?;
+        let (last, _) = self.current()?;
+        let (result, items) = update(&last);
+        drop(last);
+        let items = Arc::new(items);
+        let mut last = self.lock()?;
+        let dropped = std::mem::replace(&mut last.items, items);
+        last.seeded = true;
+        drop((last, writer, dropped));
+        Ok(result)
+    }
+}
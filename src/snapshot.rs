@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use crate::{ComparerError, HashMapComparer, MapLike};
+
+/// Copy of last hashmap of a comparer together with its generation, see `HashMapComparer::to_snapshot()`
+///
+/// With the `serde` feature it can be serialized, e.g. to keep the baseline in a format of your choice.
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::HashMapComparer;
+///
+///   let comparer = HashMapComparer::<u8, &str>::new();
+///   comparer.update(&HashMap::from([(1, "foo")]));
+///   let snapshot = comparer.to_snapshot();
+///   assert_eq!(1, snapshot.generation);
+///
+///   let restored = HashMapComparer::<u8, &str>::new();
+///   restored.restore(snapshot).unwrap();
+///   assert!(restored.is_same(&HashMap::from([(1, "foo")])));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot<K: Eq + Hash, V> {
+    pub map: HashMap<K, V>,
+    /// False if the comparer was never updated, see `HashMapComparer::is_seeded()`
+    pub seeded: bool,
+    pub generation: u64,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> HashMapComparer<K, V> {
+    /// Copies last hashmap together with its generation
+    pub fn to_snapshot(&self) -> Snapshot<K, V> {
+        crate::or_panic(self.try_to_snapshot())
+    }
+
+    /// Same as `to_snapshot()`, but returns an error instead of panicking
+    pub fn try_to_snapshot(&self) -> Result<Snapshot<K, V>, ComparerError> {
+        let (map, seeded, generation) = {
+            let baseline = self.lock()?;
+            (baseline.map.clone(), baseline.seeded, baseline.generation)
+        };
+        Ok(Snapshot {
+            map: HashMap::clone(&map),
+            seeded,
+            generation,
+        })
+    }
+
+    /// Replaces last hashmap with a snapshot, continuing from its generation.
+    /// Meant to be called on startup: history is cleared and subscribers aren't called.
+    /// Keys ignored by the key filter are dropped and the key limit applies like for an update.
+    pub fn restore(&self, snapshot: Snapshot<K, V>) -> Result<(), ComparerError> {
+        let writer = self.lock_writer()?;
+        let (_, _, settings) = self.current()?;
+        let map: HashMap<K, V> = settings
+            .key_filter
+            .view(&snapshot.map)
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        settings.check_capacity(map.len())?;
+        self.lock()?
+            .restore(map, snapshot.seeded, snapshot.generation);
+        self.updated(writer, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+use crate::ChangeSet;
+
+type Callback<K, V> = Arc<dyn Fn(&ChangeSet<K, V>) + Send + Sync>;
+type KeyPredicate<K> = Arc<dyn Fn(&K) -> bool + Send + Sync>;
+
+/// Handle of a callback registered on a comparer, pass it to `HashMapComparer::unsubscribe()` to remove the callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subscription(u64);
+
+/// Which keys a subscriber wants to hear about
+enum Interest<K> {
+    All,
+    Key(K),
+    Matching(KeyPredicate<K>),
+}
+
+struct Subscriber<K, V> {
+    id: u64,
+    interest: Interest<K>,
+    callback: Callback<K, V>,
+}
+
+struct Registry<K, V> {
+    next_id: u64,
+    subscribers: Vec<Subscriber<K, V>>,
+}
+
+/// Changes waiting to be delivered, in the order of the updates that made them
+struct Delivery<K, V> {
+    pending: VecDeque<ChangeSet<K, V>>,
+    /// Set while a thread is calling subscribers, other threads leave their changes to it
+    delivering: bool,
+}
+
+/// Callbacks registered on a comparer and shared by all of its clones
+pub(crate) struct Subscribers<K, V> {
+    registry: Mutex<Registry<K, V>>,
+    delivery: Mutex<Delivery<K, V>>,
+}
+
+impl<K, V> Subscribers<K, V> {
+    pub(crate) fn new() -> Self {
+        Self {
+            registry: Mutex::new(Registry {
+                next_id: 0,
+                subscribers: Vec::new(),
+            }),
+            delivery: Mutex::new(Delivery {
+                pending: VecDeque::new(),
+                delivering: false,
+            }),
+        }
+    }
+
+    /// Returns true if anybody has to be notified about changes
+    pub(crate) fn is_active(&self) -> bool {
+        !self.registry.lock().unwrap().subscribers.is_empty()
+    }
+
+    pub(crate) fn subscribe_all(&self, callback: Callback<K, V>) -> Subscription {
+        self.add(Interest::All, callback)
+    }
+
+    pub(crate) fn subscribe_key(&self, key: K, callback: Callback<K, V>) -> Subscription {
+        self.add(Interest::Key(key), callback)
+    }
+
+    pub(crate) fn subscribe_matching(
+        &self,
+        predicate: KeyPredicate<K>,
+        callback: Callback<K, V>,
+    ) -> Subscription {
+        self.add(Interest::Matching(predicate), callback)
+    }
+
+    pub(crate) fn unsubscribe(&self, subscription: Subscription) -> bool {
+        let mut registry = self.registry.lock().unwrap();
+        let before = registry.subscribers.len();
+        registry
+            .subscribers
+            .retain(|subscriber| subscriber.id != subscription.0);
+        registry.subscribers.len() != before
+    }
+
+    fn add(&self, interest: Interest<K>, callback: Callback<K, V>) -> Subscription {
+        let mut registry = self.registry.lock().unwrap();
+        let id = registry.next_id;
+        registry.next_id += 1;
+        registry.subscribers.push(Subscriber {
+            id,
+            interest,
+            callback,
+        });
+        Subscription(id)
+    }
+}
+
+impl<K: Clone + PartialEq, V: Clone> Subscribers<K, V> {
+    /// Queues changes for delivery. Called while updates are locked out, so the queue is in generation order
+    pub(crate) fn enqueue(&self, changes: ChangeSet<K, V>) {
+        if !changes.is_empty() {
+            self.delivery.lock().unwrap().pending.push_back(changes);
+        }
+    }
+
+    /// Delivers queued changes in order. If another thread (or a callback up the stack) is already
+    /// delivering, returns right away and leaves the queued changes to it, so callbacks never see
+    /// a later generation before an earlier one and may update the comparer themselves.
+    pub(crate) fn deliver(&self) {
+        let mut delivery = self.delivery.lock().unwrap();
+        if delivery.delivering {
+            return;
+        }
+        delivery.delivering = true;
+        while let Some(changes) = delivery.pending.pop_front() {
+            drop(delivery);
+            self.notify(&changes);
+            delivery = self.delivery.lock().unwrap();
+        }
+        delivery.delivering = false;
+    }
+
+    /// Calls every subscriber interested in at least one of the changes.
+    /// Callbacks and predicates are called without holding any lock,
+    /// a subscriber whose callback or predicate panics is unsubscribed.
+    fn notify(&self, changes: &ChangeSet<K, V>) {
+        if changes.is_empty() {
+            return;
+        }
+        let subscribers: Vec<(u64, Interest<K>, Callback<K, V>)> = {
+            let registry = self.registry.lock().unwrap();
+            registry
+                .subscribers
+                .iter()
+                .map(|subscriber| {
+                    let interest = match &subscriber.interest {
+                        Interest::All => Interest::All,
+                        Interest::Key(key) => Interest::Key(key.clone()),
+                        Interest::Matching(predicate) => Interest::Matching(predicate.clone()),
+                    };
+                    (subscriber.id, interest, subscriber.callback.clone())
+                })
+                .collect()
+        };
+        for (id, interest, callback) in subscribers {
+            let notified = panic::catch_unwind(AssertUnwindSafe(|| {
+                let filtered = match interest {
+                    Interest::All => None,
+                    Interest::Key(key) => Some(filter(changes, |changed| *changed == key)),
+                    Interest::Matching(predicate) => Some(filter(changes, |key| predicate(key))),
+                };
+                match filtered {
+                    None => callback(changes),
+                    Some(filtered) if !filtered.is_empty() => callback(&filtered),
+                    Some(_) => {}
+                }
+            }));
+            if notified.is_err() {
+                self.unsubscribe(Subscription(id));
+            }
+        }
+    }
+}
+
+fn filter<K: Clone, V: Clone>(
+    changes: &ChangeSet<K, V>,
+    mut wanted: impl FnMut(&K) -> bool,
+) -> ChangeSet<K, V> {
+    changes
+        .iter()
+        .filter(|(key, _)| wanted(key))
+        .map(|(key, change)| (key.clone(), change.clone()))
+        .collect()
+}
+
+impl<K, V> fmt::Debug for Subscribers<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self
+            .registry
+            .lock()
+            .map_or(0, |registry| registry.subscribers.len());
+        f.debug_struct("Subscribers")
+            .field("count", &count)
+            .finish()
+    }
+}
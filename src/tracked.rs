@@ -0,0 +1,203 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::Deref;
+
+use crate::{Change, ChangeSet, ComparerError, HashMapComparer};
+
+/// HashMap wrapper that records changes as they are made instead of diffing the whole hashmap
+///
+/// Every key touched through `insert()`, `remove()`, `get_mut()`, `entry()` or `clear()` remembers
+/// its value from before the first touch, so collecting the changes with `take_changes()` or `sync()`
+/// costs O(touched keys). Reading is available through `Deref` to the wrapped `HashMap`.
+/// # Examples
+/// ```
+///   use std::collections::HashMap;
+///   use comparer::{Change, HashMapComparer, TrackedMap};
+///
+///   let comparer = HashMapComparer::<u8, u32>::new();
+///   let mut tracked = TrackedMap::from(HashMap::from([(1, 10), (2, 20)]));
+///   // The first sync stores the whole hashmap in the comparer
+///   tracked.sync(&comparer);
+///
+///   tracked.insert(3, 30);
+///   *tracked.get_mut(&1).unwrap() += 1;
+///   *tracked.entry(2).or_insert(0) += 0;
+///   tracked.remove(&3);
+///
+///   let changes = tracked.sync(&comparer);
+///   assert_eq!(1, changes.len());
+///   assert_eq!(Some(&Change::Modified { old: 10, new: 11 }), changes.get(&1));
+///   assert_eq!(HashMap::from([(1, 11), (2, 20)]), comparer.clone_last());
+/// ```
+#[derive(Debug, Clone)]
+pub struct TrackedMap<K, V> {
+    map: HashMap<K, V>,
+    /// Value of every touched key from before it was first touched
+    original: HashMap<K, Option<V>>,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> TrackedMap<K, V> {
+    /// Creates empty tracked hashmap
+    pub fn new() -> Self {
+        Self::from(HashMap::new())
+    }
+
+    /// Inserts a value, returns the previous one
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.touch(&key);
+        self.map.insert(key, value)
+    }
+
+    /// Removes a key, returns its value
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        if self.map.contains_key(key) {
+            self.touch(key);
+        }
+        self.map.remove(key)
+    }
+
+    /// Returns mutable reference to a value, the key is treated as changed until `take_changes()` checks it
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        if self.map.contains_key(key) {
+            self.touch(key);
+        }
+        self.map.get_mut(key)
+    }
+
+    /// Returns entry of a key, the key is treated as changed until `take_changes()` checks it
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        self.touch(&key);
+        self.map.entry(key)
+    }
+
+    /// Removes every key
+    pub fn clear(&mut self) {
+        let keys: Vec<K> = self.map.keys().cloned().collect();
+        for key in &keys {
+            self.touch(key);
+        }
+        self.map.clear();
+    }
+
+    /// Returns true if any key was touched since changes were taken the last time
+    pub fn is_touched(&self) -> bool {
+        !self.original.is_empty()
+    }
+
+    /// Returns changes made since they were taken the last time, comparing values with `==`
+    pub fn take_changes(&mut self) -> ChangeSet<K, V>
+    where
+        V: PartialEq,
+    {
+        self.take_changes_with(|a, b| a == b)
+    }
+
+    /// Returns changes made since they were taken the last time, comparing values with a custom function
+    pub fn take_changes_with(&mut self, eq: impl Fn(&V, &V) -> bool) -> ChangeSet<K, V> {
+        let changes = self.pending_changes(eq);
+        self.original.clear();
+        changes
+    }
+
+    /// Hands changes made since the last sync to the comparer and returns them.
+    /// Values are compared with the comparer's equality function.
+    ///
+    /// If comparer has no baseline yet, the whole hashmap becomes its baseline like with `update_and_diff()`.
+    /// Otherwise only the changes are applied with `HashMapComparer::apply()`, which assumes that last hashmap
+    /// of the comparer matched this hashmap at the previous sync. See `apply()` for when that copies last hashmap.
+    pub fn sync(&mut self, comparer: &HashMapComparer<K, V>) -> ChangeSet<K, V> {
+        crate::or_panic(self.try_sync(comparer))
+    }
+
+    /// Same as `sync()`, but returns an error instead of panicking.
+    /// Changes are only forgotten once the comparer accepted them, so a failed sync can be retried.
+    /// # Examples
+    /// ```
+    ///   use std::collections::HashMap;
+    ///   use comparer::{ComparerError, HashMapComparer, TrackedMap};
+    ///
+    ///   let comparer = HashMapComparer::<u8, &str>::new().with_max_keys(1);
+    ///   let mut tracked = TrackedMap::from(HashMap::from([(1, "foo")]));
+    ///   tracked.try_sync(&comparer).unwrap();
+    ///
+    ///   tracked.insert(2, "bar");
+    ///   assert_eq!(
+    ///       Err(ComparerError::CapacityExceeded { limit: 1, len: 2 }),
+    ///       tracked.try_sync(&comparer)
+    ///   );
+    ///
+    ///   tracked.remove(&1);
+    ///   let changes = tracked.try_sync(&comparer).unwrap();
+    ///   assert_eq!(2, changes.len());
+    ///   assert_eq!(HashMap::from([(2, "bar")]), comparer.clone_last());
+    /// ```
+    pub fn try_sync(
+        &mut self,
+        comparer: &HashMapComparer<K, V>,
+    ) -> Result<ChangeSet<K, V>, ComparerError> {
+        let changes = if !comparer.try_is_seeded()? {
+            comparer.try_update_and_diff(&self.map)?
+        } else {
+            let changes = self.pending_changes(comparer.eq_fn());
+            comparer.try_apply(&changes)?
+        };
+        self.original.clear();
+        Ok(changes)
+    }
+
+    /// Returns the wrapped hashmap, dropping changes that weren't taken
+    pub fn into_inner(self) -> HashMap<K, V> {
+        self.map
+    }
+
+    /// Changes made since they were taken the last time, without forgetting them
+    fn pending_changes(&self, eq: impl Fn(&V, &V) -> bool) -> ChangeSet<K, V> {
+        let mut changes = ChangeSet::new();
+        for (key, original) in &self.original {
+            let change = match (original, self.map.get(key)) {
+                (None, None) => continue,
+                (None, Some(new)) => Change::Added(new.clone()),
+                (Some(old), None) => Change::Removed(old.clone()),
+                (Some(old), Some(new)) if eq(old, new) => continue,
+                (Some(old), Some(new)) => Change::Modified {
+                    old: old.clone(),
+                    new: new.clone(),
+                },
+            };
+            changes.push(key.clone(), change);
+        }
+        changes
+    }
+
+    fn touch(&mut self, key: &K) {
+        if !self.original.contains_key(key) {
+            self.original
+                .insert(key.clone(), self.map.get(key).cloned());
+        }
+    }
+}
+
+/// Wraps a hashmap without any recorded changes
+impl<K, V> From<HashMap<K, V>> for TrackedMap<K, V> {
+    fn from(map: HashMap<K, V>) -> Self {
+        Self {
+            map,
+            original: HashMap::new(),
+        }
+    }
+}
+
+impl<K, V> Deref for TrackedMap<K, V> {
+    type Target = HashMap<K, V>;
+
+    fn deref(&self) -> &HashMap<K, V> {
+        &self.map
+    }
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> Default for TrackedMap<K, V> {
+    fn default() -> Self {
+        TrackedMap::new()
+    }
+}